@@ -2,7 +2,7 @@
 D=A
 @SP
 M=D
-@FibonacciElement.$ret
+@FibonacciElement$ret.0
 D=A
 @SP
 A=M
@@ -49,7 +49,7 @@ D=M
 M=D
 @Sys.init
 0;JMP
-(FibonacciElement.$ret)
+(FibonacciElement$ret.0)
 (Sys.init)
 @0
 D=A
@@ -60,7 +60,7 @@ A=M
 M=D
 @SP
 M=M+1
-@Sys.init$ret.1
+@Sys.init$ret.0
 D=A
 @SP
 A=M
@@ -107,9 +107,9 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(Sys.init$ret.1)
-(Main.fibonacci$END)
-@Main.fibonacci$END
+(Sys.init$ret.0)
+(Sys.init$END)
+@Sys.init$END
 0;JMP
 (Main.fibonacci)
 @0
@@ -136,14 +136,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@lesser.3
+@lesser.0
 D;JLT
 D=0
-@done.3
+@done.0
 0;JMP
-(lesser.3)
+(lesser.0)
 D=-1
-(done.3)
+(done.0)
 @SP
 A=M-1
 M=D
@@ -230,7 +230,7 @@ AM=M-1
 D=M
 A=A-1
 M=M-D
-@Main.fibonacci$ret.1
+@Main.fibonacci$ret.0
 D=A
 @SP
 A=M
@@ -277,7 +277,7 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(Main.fibonacci$ret.1)
+(Main.fibonacci$ret.0)
 @ARG
 D=M
 @0
@@ -300,7 +300,7 @@ AM=M-1
 D=M
 A=A-1
 M=M-D
-@Main.fibonacci$ret.2
+@Main.fibonacci$ret.1
 D=A
 @SP
 A=M
@@ -347,7 +347,7 @@ D=M
 M=D
 @Main.fibonacci
 0;JMP
-(Main.fibonacci$ret.2)
+(Main.fibonacci$ret.1)
 @SP
 AM=M-1
 D=M
@@ -2,7 +2,7 @@
 D=A
 @SP
 M=D
-@NestedCall.$ret
+@NestedCall$ret.0
 D=A
 @SP
 A=M
@@ -49,7 +49,7 @@ D=M
 M=D
 @Sys.init
 0;JMP
-(NestedCall.$ret)
+(NestedCall$ret.0)
 (Sys.init)
 @0
 D=A
@@ -77,7 +77,7 @@ AM=M-1
 D=M
 @THAT
 M=D
-@Sys.init$ret.1
+@Sys.init$ret.0
 D=A
 @SP
 A=M
@@ -124,7 +124,7 @@ D=M
 M=D
 @Sys.main
 0;JMP
-(Sys.init$ret.1)
+(Sys.init$ret.0)
 @5
 D=A
 @1
@@ -137,8 +137,8 @@ D=M
 @R13
 A=M
 M=D
-(Sys.main$LOOP)
-@Sys.main$LOOP
+(Sys.init$LOOP)
+@Sys.init$LOOP
 0;JMP
 (Sys.main)
 @0
@@ -256,7 +256,7 @@ A=M
 M=D
 @SP
 M=M+1
-@Sys.main$ret.1
+@Sys.main$ret.0
 D=A
 @SP
 A=M
@@ -303,7 +303,7 @@ D=M
 M=D
 @Sys.add12
 0;JMP
-(Sys.main$ret.1)
+(Sys.main$ret.0)
 @5
 D=A
 @0
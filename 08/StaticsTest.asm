@@ -2,7 +2,7 @@
 D=A
 @SP
 M=D
-@StaticsTest.$ret
+@StaticsTest$ret.0
 D=A
 @SP
 A=M
@@ -49,7 +49,7 @@ D=M
 M=D
 @Sys.init
 0;JMP
-(StaticsTest.$ret)
+(StaticsTest$ret.0)
 (Sys.init)
 @0
 D=A
@@ -67,7 +67,7 @@ A=M
 M=D
 @SP
 M=M+1
-@Sys.init$ret.1
+@Sys.init$ret.0
 D=A
 @SP
 A=M
@@ -114,7 +114,7 @@ D=M
 M=D
 @Class1.set
 0;JMP
-(Sys.init$ret.1)
+(Sys.init$ret.0)
 @5
 D=A
 @0
@@ -141,7 +141,7 @@ A=M
 M=D
 @SP
 M=M+1
-@Class1.set$ret.1
+@Sys.init$ret.1
 D=A
 @SP
 A=M
@@ -188,7 +188,7 @@ D=M
 M=D
 @Class2.set
 0;JMP
-(Class1.set$ret.1)
+(Sys.init$ret.1)
 @5
 D=A
 @0
@@ -201,7 +201,7 @@ D=M
 @R13
 A=M
 M=D
-@Class2.set$ret.1
+@Sys.init$ret.2
 D=A
 @SP
 A=M
@@ -248,8 +248,8 @@ D=M
 M=D
 @Class1.get
 0;JMP
-(Class2.set$ret.1)
-@Class1.get$ret.1
+(Sys.init$ret.2)
+@Sys.init$ret.3
 D=A
 @SP
 A=M
@@ -296,9 +296,9 @@ D=M
 M=D
 @Class2.get
 0;JMP
-(Class1.get$ret.1)
-(Class2.get$END)
-@Class2.get$END
+(Sys.init$ret.3)
+(Sys.init$END)
+@Sys.init$END
 0;JMP
 (Class1.set)
 @0
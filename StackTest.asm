@@ -17,14 +17,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@equal.2
+@equal.0
 D;JEQ
 D=0
-@done.2
+@done.0
 0;JMP
-(equal.2)
+(equal.0)
 D=-1
-(done.2)
+(done.0)
 @SP
 A=M-1
 M=D
@@ -47,14 +47,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@equal.5
+@equal.1
 D;JEQ
 D=0
-@done.5
+@done.1
 0;JMP
-(equal.5)
+(equal.1)
 D=-1
-(done.5)
+(done.1)
 @SP
 A=M-1
 M=D
@@ -77,14 +77,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@equal.8
+@equal.2
 D;JEQ
 D=0
-@done.8
+@done.2
 0;JMP
-(equal.8)
+(equal.2)
 D=-1
-(done.8)
+(done.2)
 @SP
 A=M-1
 M=D
@@ -107,14 +107,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@lesser.11
+@lesser.3
 D;JLT
 D=0
-@done.11
+@done.3
 0;JMP
-(lesser.11)
+(lesser.3)
 D=-1
-(done.11)
+(done.3)
 @SP
 A=M-1
 M=D
@@ -137,14 +137,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@lesser.14
+@lesser.4
 D;JLT
 D=0
-@done.14
+@done.4
 0;JMP
-(lesser.14)
+(lesser.4)
 D=-1
-(done.14)
+(done.4)
 @SP
 A=M-1
 M=D
@@ -167,14 +167,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@lesser.17
+@lesser.5
 D;JLT
 D=0
-@done.17
+@done.5
 0;JMP
-(lesser.17)
+(lesser.5)
 D=-1
-(done.17)
+(done.5)
 @SP
 A=M-1
 M=D
@@ -197,14 +197,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@greater.20
+@greater.6
 D;JGT
 D=0
-@done.20
+@done.6
 0;JMP
-(greater.20)
+(greater.6)
 D=-1
-(done.20)
+(done.6)
 @SP
 A=M-1
 M=D
@@ -227,14 +227,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@greater.23
+@greater.7
 D;JGT
 D=0
-@done.23
+@done.7
 0;JMP
-(greater.23)
+(greater.7)
 D=-1
-(done.23)
+(done.7)
 @SP
 A=M-1
 M=D
@@ -257,14 +257,14 @@ AM=M-1
 D=M
 A=A-1
 D=M-D
-@greater.26
+@greater.8
 D;JGT
 D=0
-@done.26
+@done.8
 0;JMP
-(greater.26)
+(greater.8)
 D=-1
-(done.26)
+(done.8)
 @SP
 A=M-1
 M=D
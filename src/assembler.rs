@@ -0,0 +1,406 @@
+use crate::{cpu::ROM_SIZE, Diagnostics, Span, TranslateError};
+use std::collections::HashMap;
+
+// first free ram address for variables, after R0 - R15
+const VARIABLE_BASE_ADDRESS: u16 = 16;
+
+pub struct HackAssembler;
+
+impl Default for HackAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HackAssembler {
+    pub fn new() -> HackAssembler {
+        HackAssembler
+    }
+
+    // assembles hack assembly into binary text, one 16 bit instruction per line
+    pub fn assemble(&self, asm_code: &str, file_name: &str) -> Result<String, Diagnostics> {
+        self.assemble_with_symbols(asm_code, file_name)
+            .map(|(machine_code, _)| machine_code)
+    }
+
+    // also gives back the address every label and variable ended up at
+    pub(crate) fn assemble_with_symbols(
+        &self,
+        asm_code: &str,
+        file_name: &str,
+    ) -> Result<(String, HashMap<String, u16>), Diagnostics> {
+        let instructions = self.clean_asm_code(asm_code, file_name);
+        let mut symbol_table = self.get_predefined_symbol_table();
+        let mut diagnostics = Diagnostics::new();
+
+        // first pass, labels are bound to the address of the next instruction
+        let mut rom_address: usize = 0;
+        for (instruction, span) in &instructions {
+            if let Some(label_name) = instruction
+                .strip_prefix('(')
+                .and_then(|label| label.strip_suffix(')'))
+            {
+                if !self.is_valid_symbol(label_name) {
+                    diagnostics.push(self.invalid_instruction(instruction, span));
+                } else if symbol_table.contains_key(label_name) {
+                    diagnostics.push(TranslateError::DuplicateLabel {
+                        label: label_name.to_string(),
+                        span: span.clone(),
+                    });
+                } else {
+                    // at most ROM_SIZE, anything above is reported below
+                    let label_address = rom_address.min(ROM_SIZE) as u16;
+                    symbol_table.insert(label_name.to_string(), label_address);
+                }
+            } else {
+                rom_address += 1;
+            }
+        }
+        // labels past the end of the rom would not fit into an a-instruction either
+        if rom_address > ROM_SIZE {
+            diagnostics.push(TranslateError::RomOverflow {
+                instructions: rom_address,
+            });
+            return Err(diagnostics);
+        }
+
+        // second pass, variables get the next free ram address in order of appearance
+        let mut machine_code: Vec<String> = Vec::new();
+        let mut variable_address = VARIABLE_BASE_ADDRESS;
+        for (instruction, span) in &instructions {
+            if instruction.starts_with('(') {
+                continue;
+            }
+
+            let binary_instruction = if let Some(symbol) = instruction.strip_prefix('@') {
+                self.write_a_instruction(symbol, &mut symbol_table, &mut variable_address)
+            } else {
+                self.write_c_instruction(instruction)
+            };
+            match binary_instruction {
+                Some(binary_instruction) => machine_code.push(binary_instruction),
+                None => diagnostics.push(self.invalid_instruction(instruction, span)),
+            }
+        }
+
+        if diagnostics.is_empty() {
+            Ok((machine_code.join("\n"), symbol_table))
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    // removes comments and all whitespace, keeping where each instruction came from
+    fn clean_asm_code(&self, asm_code: &str, file_name: &str) -> Vec<(String, Span)> {
+        const COMMENTS: &str = "//";
+        let mut instructions = Vec::new();
+        for (line_index, current_line) in asm_code.lines().enumerate() {
+            let asm_code_before_comment = match current_line.find(COMMENTS) {
+                Some(comment_start) => &current_line[..comment_start],
+                None => current_line,
+            };
+            let instruction: String = asm_code_before_comment
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            if instruction.is_empty() {
+                continue;
+            }
+
+            let leading_whitespace =
+                asm_code_before_comment.len() - asm_code_before_comment.trim_start().len();
+            let span = Span {
+                file: file_name.to_string(),
+                line: line_index + 1,
+                column: current_line[..leading_whitespace].chars().count() + 1,
+                length: asm_code_before_comment.trim().chars().count(),
+                source_line: current_line.to_string(),
+            };
+            instructions.push((instruction, span));
+        }
+
+        instructions
+    }
+
+    fn get_predefined_symbol_table(&self) -> HashMap<String, u16> {
+        let mut symbol_table: HashMap<String, u16> = HashMap::new();
+        for register in 0..16 {
+            symbol_table.insert(format!("R{register}"), register);
+        }
+        symbol_table.insert(String::from("SP"), 0);
+        symbol_table.insert(String::from("LCL"), 1);
+        symbol_table.insert(String::from("ARG"), 2);
+        symbol_table.insert(String::from("THIS"), 3);
+        symbol_table.insert(String::from("THAT"), 4);
+        symbol_table.insert(String::from("SCREEN"), 16384);
+        symbol_table.insert(String::from("KBD"), 24576);
+        symbol_table
+    }
+
+    fn is_valid_symbol(&self, symbol: &str) -> bool {
+        symbol.chars().next().is_some_and(|c| !c.is_ascii_digit())
+            && symbol
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c))
+    }
+
+    fn write_a_instruction(
+        &self,
+        symbol: &str,
+        symbol_table: &mut HashMap<String, u16>,
+        variable_address: &mut u16,
+    ) -> Option<String> {
+        let address = if symbol.starts_with(|c: char| c.is_ascii_digit()) {
+            symbol
+                .parse::<u16>()
+                .ok()
+                .filter(|&address| address < 0x8000)?
+        } else if let Some(&address) = symbol_table.get(symbol) {
+            // a label right after the last instruction of a full rom
+            Some(address).filter(|&address| address < 0x8000)?
+        } else if self.is_valid_symbol(symbol) {
+            let address = *variable_address;
+            symbol_table.insert(symbol.to_string(), address);
+            *variable_address += 1;
+            address
+        } else {
+            return None;
+        };
+
+        Some(format!("{address:016b}"))
+    }
+
+    fn write_c_instruction(&self, instruction: &str) -> Option<String> {
+        let (dest, comp_and_jump) = match instruction.split_once('=') {
+            Some((dest, comp_and_jump)) => (dest, comp_and_jump),
+            None => ("", instruction),
+        };
+        let (comp, jump) = match comp_and_jump.split_once(';') {
+            Some((comp, jump)) => (comp, jump),
+            None => (comp_and_jump, ""),
+        };
+
+        let comp_bits = self.comp(comp)?;
+        let dest_bits = self.dest(dest)?;
+        let jump_bits = self.jump(jump)?;
+        Some(format!("111{comp_bits}{dest_bits}{jump_bits}"))
+    }
+
+    // a bit followed by the six alu control bits
+    fn comp(&self, comp: &str) -> Option<&'static str> {
+        match comp {
+            "0" => Some("0101010"),
+            "1" => Some("0111111"),
+            "-1" => Some("0111010"),
+            "D" => Some("0001100"),
+            "A" => Some("0110000"),
+            "M" => Some("1110000"),
+            "!D" => Some("0001101"),
+            "!A" => Some("0110001"),
+            "!M" => Some("1110001"),
+            "-D" => Some("0001111"),
+            "-A" => Some("0110011"),
+            "-M" => Some("1110011"),
+            "D+1" | "1+D" => Some("0011111"),
+            "A+1" | "1+A" => Some("0110111"),
+            "M+1" | "1+M" => Some("1110111"),
+            "D-1" => Some("0001110"),
+            "A-1" => Some("0110010"),
+            "M-1" => Some("1110010"),
+            "D+A" | "A+D" => Some("0000010"),
+            "D+M" | "M+D" => Some("1000010"),
+            "D-A" => Some("0010011"),
+            "D-M" => Some("1010011"),
+            "A-D" => Some("0000111"),
+            "M-D" => Some("1000111"),
+            "D&A" | "A&D" => Some("0000000"),
+            "D&M" | "M&D" => Some("1000000"),
+            "D|A" | "A|D" => Some("0010101"),
+            "D|M" | "M|D" => Some("1010101"),
+            _ => None,
+        }
+    }
+
+    // any order of A, D and M is accepted, each register at most once
+    fn dest(&self, dest: &str) -> Option<String> {
+        let mut dest_bits = [b'0'; 3];
+        for register in dest.chars() {
+            let bit = match register {
+                'A' => 0,
+                'D' => 1,
+                'M' => 2,
+                _ => return None,
+            };
+            if dest_bits[bit] == b'1' {
+                return None;
+            }
+            dest_bits[bit] = b'1';
+        }
+
+        String::from_utf8(dest_bits.to_vec()).ok()
+    }
+
+    fn jump(&self, jump: &str) -> Option<&'static str> {
+        match jump {
+            "" => Some("000"),
+            "JGT" => Some("001"),
+            "JEQ" => Some("010"),
+            "JGE" => Some("011"),
+            "JLT" => Some("100"),
+            "JNE" => Some("101"),
+            "JLE" => Some("110"),
+            "JMP" => Some("111"),
+            _ => None,
+        }
+    }
+
+    fn invalid_instruction(&self, instruction: &str, span: &Span) -> TranslateError {
+        TranslateError::InvalidInstruction {
+            instruction: instruction.to_string(),
+            span: span.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble_fixture(asm_code: &str) -> String {
+        HackAssembler::new()
+            .assemble(asm_code, "fixture.asm")
+            .expect("Should be valid")
+    }
+
+    #[test]
+    fn assemble_matches_hack_fixtures() {
+        let fixtures = [
+            (
+                include_str!("../SimpleAdd.asm"),
+                include_str!("../SimpleAdd.hack"),
+            ),
+            (
+                include_str!("../StackTest.asm"),
+                include_str!("../StackTest.hack"),
+            ),
+            (
+                include_str!("../BasicTest.asm"),
+                include_str!("../BasicTest.hack"),
+            ),
+            (
+                include_str!("../PointerTest.asm"),
+                include_str!("../PointerTest.hack"),
+            ),
+            (
+                include_str!("../StaticTest.asm"),
+                include_str!("../StaticTest.hack"),
+            ),
+            (
+                include_str!("../08/BasicLoop.asm"),
+                include_str!("../08/BasicLoop.hack"),
+            ),
+            (
+                include_str!("../08/FibonacciSeries.asm"),
+                include_str!("../08/FibonacciSeries.hack"),
+            ),
+            (
+                include_str!("../08/SimpleFunction.asm"),
+                include_str!("../08/SimpleFunction.hack"),
+            ),
+            (
+                include_str!("../08/NestedCall.asm"),
+                include_str!("../08/NestedCall.hack"),
+            ),
+            (
+                include_str!("../08/FibonacciElement.asm"),
+                include_str!("../08/FibonacciElement.hack"),
+            ),
+            (
+                include_str!("../08/StaticsTest.asm"),
+                include_str!("../08/StaticsTest.hack"),
+            ),
+        ];
+
+        for (asm_code, hack_code) in fixtures {
+            assert_eq!(hack_code, assemble_fixture(asm_code));
+        }
+    }
+
+    #[test]
+    fn assemble_symbols_and_instructions() {
+        let asm_code =
+            "// comment\n(LOOP)\n  @counter // variable\n  M = M+1\n@LOOP\n0;JMP\n@R15\nDM=D|A";
+
+        assert_eq!(
+            "0000000000010000\n1111110111001000\n0000000000000000\n1110101010000111\n0000000000001111\n1110010101011000",
+            assemble_fixture(asm_code)
+        );
+    }
+
+    #[test]
+    fn assemble_invalid_instructions() {
+        let diagnostics = HackAssembler::new()
+            .assemble("@40000\nD=X\n0;JUMP\n(1ABEL)", "Bad.asm")
+            .expect_err("Should be invalid");
+        let lines: Vec<usize> = diagnostics
+            .iter()
+            .filter_map(|error| error.span())
+            .map(|span| span.line)
+            .collect();
+
+        assert_eq!(vec![4, 1, 2, 3], lines);
+    }
+
+    #[test]
+    fn assemble_duplicate_labels_and_rom_overflow() {
+        let diagnostics = HackAssembler::new()
+            .assemble(
+                "(LOOP)
+@LOOP
+(LOOP)
+0;JMP
+(SP)",
+                "Bad.asm",
+            )
+            .expect_err("Should be invalid");
+        let errors: Vec<(String, usize)> = diagnostics
+            .iter()
+            .map(|error| {
+                let message = error.to_string();
+                let message = message.lines().next().unwrap().to_string();
+                (message, error.span().unwrap().line)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (String::from("error: duplicate label `LOOP`"), 3),
+                (String::from("error: duplicate label `SP`"), 5),
+            ],
+            errors
+        );
+
+        // a full rom still assembles, but not one more instruction or a jump past its end
+        let full_rom = "D=0\n".repeat(ROM_SIZE);
+        assert!(HackAssembler::new().assemble(&full_rom, "Full.asm").is_ok());
+        let diagnostics = HackAssembler::new()
+            .assemble(&format!("{full_rom}0;JMP"), "Full.asm")
+            .expect_err("Should be invalid");
+        assert!(matches!(
+            diagnostics.iter().next(),
+            Some(TranslateError::RomOverflow {
+                instructions: 32769
+            })
+        ));
+        let diagnostics = HackAssembler::new()
+            .assemble(&format!("@END\n{}(END)", &full_rom[4..]), "Full.asm")
+            .expect_err("Should be invalid");
+        assert_eq!(
+            Some(1),
+            diagnostics
+                .iter()
+                .next()
+                .and_then(|error| error.span())
+                .map(|span| span.line)
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use crate::emit::json_string;
+use crate::{VmCommand, VmFile};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FunctionNode {
+    pub name: String,
+    // None for functions that are called but not declared in any of the files,
+    // such as os functions that are left out
+    pub file: Option<String>,
+    pub nlocals: u16,
+    // calls itself directly or through other functions
+    pub recursive: bool,
+}
+
+// every call from one function to another with the same number of arguments
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CallEdge {
+    pub caller: String,
+    pub callee: String,
+    pub nargs: u16,
+    pub call_sites: usize,
+}
+
+// the node of the code outside of any function in a file, named so it can not
+// be mistaken for a function
+pub(crate) fn file_node_name(file_name: &str) -> String {
+    format!("<{file_name}.vm>")
+}
+
+// the static call graph of a program, calls outside of any function
+// have a node of the file they are in as their caller
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CallGraph {
+    pub functions: Vec<FunctionNode>,
+    pub calls: Vec<CallEdge>,
+}
+
+impl CallGraph {
+    // functions come in order of declaration, file nodes where their first call is,
+    // followed by the undeclared functions, calls in order of their first call site
+    pub fn new(vm_files: &[VmFile]) -> CallGraph {
+        let mut call_graph = CallGraph::default();
+        let mut edge_indexes: HashMap<(String, String, u16), usize> = HashMap::new();
+        for vm_file in vm_files {
+            let mut current_function = file_node_name(&vm_file.name);
+            for parsed_command in &vm_file.commands {
+                match &parsed_command.command {
+                    VmCommand::Function { name, nlocals } => {
+                        current_function = name.clone();
+                        if call_graph.function(name).is_none() {
+                            call_graph.functions.push(FunctionNode {
+                                name: name.clone(),
+                                file: Some(vm_file.name.clone()),
+                                nlocals: *nlocals,
+                                recursive: false,
+                            });
+                        }
+                    }
+                    VmCommand::Call { name, nargs } => {
+                        if call_graph.function(&current_function).is_none() {
+                            call_graph.functions.push(FunctionNode {
+                                name: current_function.clone(),
+                                file: Some(vm_file.name.clone()),
+                                nlocals: 0,
+                                recursive: false,
+                            });
+                        }
+                        let key = (current_function.clone(), name.clone(), *nargs);
+                        match edge_indexes.get(&key) {
+                            Some(&edge_index) => call_graph.calls[edge_index].call_sites += 1,
+                            None => {
+                                edge_indexes.insert(key, call_graph.calls.len());
+                                call_graph.calls.push(CallEdge {
+                                    caller: current_function.clone(),
+                                    callee: name.clone(),
+                                    nargs: *nargs,
+                                    call_sites: 1,
+                                });
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let undeclared_functions: Vec<String> = call_graph
+            .calls
+            .iter()
+            .map(|call_edge| call_edge.callee.clone())
+            .filter(|callee| call_graph.function(callee).is_none())
+            .collect();
+        for name in undeclared_functions {
+            if call_graph.function(&name).is_none() {
+                call_graph.functions.push(FunctionNode {
+                    name,
+                    file: None,
+                    nlocals: 0,
+                    recursive: false,
+                });
+            }
+        }
+        let recursive_functions: HashSet<String> = call_graph
+            .functions
+            .iter()
+            .filter(|function| {
+                call_graph
+                    .reachable_from(call_graph.callees(&function.name))
+                    .contains(&function.name)
+            })
+            .map(|function| function.name.clone())
+            .collect();
+        for function in &mut call_graph.functions {
+            function.recursive = recursive_functions.contains(&function.name);
+        }
+
+        call_graph
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionNode> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    fn callees<'a>(&'a self, caller: &'a str) -> impl Iterator<Item = &'a str> {
+        self.calls
+            .iter()
+            .filter(move |call_edge| call_edge.caller == caller)
+            .map(|call_edge| call_edge.callee.as_str())
+    }
+
+    // the roots and every function they call directly or indirectly
+    pub fn reachable_from<'a>(&self, roots: impl IntoIterator<Item = &'a str>) -> HashSet<String> {
+        let mut reachable: HashSet<String> = HashSet::new();
+        let mut pending: Vec<&str> = roots.into_iter().collect();
+        while let Some(name) = pending.pop() {
+            if reachable.insert(name.to_string()) {
+                pending.extend(self.callees(name));
+            }
+        }
+
+        reachable
+    }
+
+    // graphviz, recursive functions are drawn red, undeclared ones dashed
+    // and the code outside of functions as boxes
+    pub fn write_dot(&self, program_name: &str) -> String {
+        let mut dot = format!("digraph {} {{\n", json_string(program_name));
+        for function in &self.functions {
+            let mut attributes = match &function.file {
+                Some(file) if function.name == file_node_name(file) => String::from("shape=box"),
+                Some(_) => format!(
+                    "label={}",
+                    json_string(&format!("{}\n{} locals", function.name, function.nlocals))
+                ),
+                None => String::from("style=dashed"),
+            };
+            if function.recursive {
+                attributes.push_str(", color=red");
+            }
+            dot.push_str(&format!(
+                "  {} [{attributes}];\n",
+                json_string(&function.name)
+            ));
+        }
+        for call_edge in &self.calls {
+            let call_sites = match call_edge.call_sites {
+                1 => String::from("1 call"),
+                count => format!("{count} calls"),
+            };
+            let nargs = match call_edge.nargs {
+                1 => String::from("1 arg"),
+                count => format!("{count} args"),
+            };
+            dot.push_str(&format!(
+                "  {} -> {} [label=\"{call_sites}, {nargs}\"];\n",
+                json_string(&call_edge.caller),
+                json_string(&call_edge.callee)
+            ));
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    pub fn write_json(&self, program_name: &str) -> String {
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|function| {
+                format!(
+                    "    {{\"name\": {}, \"file\": {}, \"locals\": {}, \"recursive\": {}}}",
+                    json_string(&function.name),
+                    function
+                        .file
+                        .as_deref()
+                        .map_or(String::from("null"), json_string),
+                    function.nlocals,
+                    function.recursive
+                )
+            })
+            .collect();
+        let calls: Vec<String> = self
+            .calls
+            .iter()
+            .map(|call_edge| {
+                format!(
+                    "    {{\"caller\": {}, \"callee\": {}, \"args\": {}, \"call_sites\": {}}}",
+                    json_string(&call_edge.caller),
+                    json_string(&call_edge.callee),
+                    call_edge.nargs,
+                    call_edge.call_sites
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"program\": {},\n  \"functions\": [\n{}\n  ],\n  \"calls\": [\n{}\n  ]\n}}\n",
+            json_string(program_name),
+            functions.join(",\n"),
+            calls.join(",\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_vm_fixture;
+
+    #[test]
+    fn call_graph_of_fibonacci_element() {
+        let call_graph = CallGraph::new(&parse_vm_fixture(&[
+            ("Sys", include_str!("../08/FibonacciElement/Sys.vm")),
+            ("Main", include_str!("../08/FibonacciElement/Main.vm")),
+        ]));
+
+        assert_eq!(
+            vec![
+                FunctionNode {
+                    name: String::from("Sys.init"),
+                    file: Some(String::from("Sys")),
+                    nlocals: 0,
+                    recursive: false,
+                },
+                FunctionNode {
+                    name: String::from("Main.fibonacci"),
+                    file: Some(String::from("Main")),
+                    nlocals: 0,
+                    recursive: true,
+                },
+            ],
+            call_graph.functions
+        );
+        assert_eq!(
+            vec![
+                CallEdge {
+                    caller: String::from("Sys.init"),
+                    callee: String::from("Main.fibonacci"),
+                    nargs: 1,
+                    call_sites: 1,
+                },
+                CallEdge {
+                    caller: String::from("Main.fibonacci"),
+                    callee: String::from("Main.fibonacci"),
+                    nargs: 1,
+                    call_sites: 2,
+                },
+            ],
+            call_graph.calls
+        );
+    }
+
+    #[test]
+    fn call_graph_dot_and_json() {
+        let call_graph = CallGraph::new(&parse_vm_fixture(&[(
+            "Main",
+            "call Main.main 0\nfunction Main.main 2\ncall Math.multiply 2\ncall Math.multiply 2\nreturn",
+        )]));
+
+        assert_eq!(
+            HashSet::from([String::from("Main.main"), String::from("Math.multiply")]),
+            call_graph.reachable_from(["Main.main"])
+        );
+        assert_eq!(
+            "digraph \"Main\" {\n  \"<Main.vm>\" [shape=box];\n  \"Main.main\" [label=\"Main.main\\n2 locals\"];\n  \"Math.multiply\" [style=dashed];\n  \"<Main.vm>\" -> \"Main.main\" [label=\"1 call, 0 args\"];\n  \"Main.main\" -> \"Math.multiply\" [label=\"2 calls, 2 args\"];\n}\n",
+            call_graph.write_dot("Main")
+        );
+        assert_eq!(
+            "{\n  \"program\": \"Main\",\n  \"functions\": [\n    {\"name\": \"<Main.vm>\", \"file\": \"Main\", \"locals\": 0, \"recursive\": false},\n    {\"name\": \"Main.main\", \"file\": \"Main\", \"locals\": 2, \"recursive\": false},\n    {\"name\": \"Math.multiply\", \"file\": null, \"locals\": 0, \"recursive\": false}\n  ],\n  \"calls\": [\n    {\"caller\": \"<Main.vm>\", \"callee\": \"Main.main\", \"args\": 0, \"call_sites\": 1},\n    {\"caller\": \"Main.main\", \"callee\": \"Math.multiply\", \"args\": 2, \"call_sites\": 2}\n  ]\n}\n",
+            call_graph.write_json("Main")
+        );
+    }
+}
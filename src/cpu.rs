@@ -0,0 +1,268 @@
+use crate::{Diagnostics, HackAssembler, Span, TranslateError};
+
+pub const ROM_SIZE: usize = 32768;
+pub const RAM_SIZE: usize = 32768;
+
+// address bus of the hack computer is 15 bits wide
+const ADDRESS_MASK: u16 = 0x7FFF;
+
+// emulates the hack cpu with its instruction and data memory
+pub struct HackCpu {
+    rom: Vec<u16>,
+    ram: Vec<i16>,
+    // number of loaded instructions, the rest of rom is zero
+    program_length: usize,
+    a: i16,
+    d: i16,
+    pc: u16,
+}
+
+impl Default for HackCpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HackCpu {
+    pub fn new() -> HackCpu {
+        HackCpu {
+            rom: vec![0; ROM_SIZE],
+            ram: vec![0; RAM_SIZE],
+            program_length: 0,
+            a: 0,
+            d: 0,
+            pc: 0,
+        }
+    }
+
+    // loads assembly such as the output of translate_program
+    pub fn from_asm(asm_code: &str, file_name: &str) -> Result<HackCpu, Diagnostics> {
+        let hack_assembler = HackAssembler::new();
+        let hack_code = hack_assembler.assemble(asm_code, file_name)?;
+        HackCpu::from_hack(&hack_code, file_name)
+    }
+
+    // loads binary text with one 16 bit instruction per line
+    pub fn from_hack(hack_code: &str, file_name: &str) -> Result<HackCpu, Diagnostics> {
+        let mut diagnostics = Diagnostics::new();
+        let mut instructions: Vec<u16> = Vec::new();
+        for (line_index, current_line) in hack_code.lines().enumerate() {
+            let binary_instruction = current_line.trim();
+            if binary_instruction.is_empty() {
+                continue;
+            }
+
+            let instruction = Some(binary_instruction)
+                .filter(|instruction| instruction.len() == 16)
+                .and_then(|instruction| u16::from_str_radix(instruction, 2).ok());
+            match instruction {
+                Some(instruction) => instructions.push(instruction),
+                None => diagnostics.push(TranslateError::InvalidInstruction {
+                    instruction: binary_instruction.to_string(),
+                    span: Span {
+                        file: file_name.to_string(),
+                        line: line_index + 1,
+                        column: current_line.find(binary_instruction).unwrap_or_default() + 1,
+                        length: binary_instruction.chars().count(),
+                        source_line: current_line.to_string(),
+                    },
+                }),
+            }
+        }
+
+        if instructions.len() > ROM_SIZE {
+            diagnostics.push(TranslateError::RomOverflow {
+                instructions: instructions.len(),
+            });
+        }
+
+        if diagnostics.is_empty() {
+            let mut hack_cpu = HackCpu::new();
+            hack_cpu.rom[..instructions.len()].copy_from_slice(&instructions);
+            hack_cpu.program_length = instructions.len();
+            Ok(hack_cpu)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    pub fn ram(&self, address: u16) -> i16 {
+        self.ram[usize::from(address & ADDRESS_MASK)]
+    }
+
+    pub fn set_ram(&mut self, address: u16, value: i16) {
+        self.ram[usize::from(address & ADDRESS_MASK)] = value;
+    }
+
+    pub fn a(&self) -> i16 {
+        self.a
+    }
+
+    pub fn d(&self) -> i16 {
+        self.d
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // true when stuck in an endless @LOOP 0;JMP loop, which is how translated programs end,
+    // or when pc ran past the loaded program such as after a return without a caller
+    pub fn is_idle(&self) -> bool {
+        let is_a_instruction = |address: usize| self.rom[address] & 0x8000 == 0;
+        let pc = usize::from(self.pc);
+        if pc >= self.program_length {
+            return true;
+        }
+        let loop_start = if is_a_instruction(pc) {
+            pc
+        } else {
+            pc.saturating_sub(1)
+        };
+        let jump = self.rom[(loop_start + 1) % ROM_SIZE];
+        is_a_instruction(loop_start)
+            && usize::from(self.rom[loop_start]) == loop_start
+            && jump & 0xE007 == 0xE007
+    }
+
+    // restarts the program from the first instruction, memory is kept
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    pub fn run(&mut self, steps: usize) {
+        for _step in 0..steps {
+            self.step();
+        }
+    }
+
+    // executes the instruction at pc, same as one tick tock of the cpu emulator
+    pub fn step(&mut self) {
+        let instruction = self.rom[usize::from(self.pc & ADDRESS_MASK)];
+        if instruction & 0x8000 == 0 {
+            // a instruction
+            self.a = instruction as i16;
+            self.pc = self.pc.wrapping_add(1) & ADDRESS_MASK;
+            return;
+        }
+
+        // c instruction, 111a cccc ccdd djjj
+        // memory and jumps use the value of A before this instruction writes to it
+        let address = self.a as u16 & ADDRESS_MASK;
+        let y = if instruction & 0x1000 != 0 {
+            self.ram(address)
+        } else {
+            self.a
+        };
+        let out = self.alu(self.d, y, (instruction >> 6) & 0x3F);
+
+        if instruction & 0x0008 != 0 {
+            self.set_ram(address, out);
+        }
+        if instruction & 0x0020 != 0 {
+            self.a = out;
+        }
+        if instruction & 0x0010 != 0 {
+            self.d = out;
+        }
+
+        let jump = (instruction & 0x0004 != 0 && out < 0)
+            || (instruction & 0x0002 != 0 && out == 0)
+            || (instruction & 0x0001 != 0 && out > 0);
+        if jump {
+            self.pc = address;
+        } else {
+            self.pc = self.pc.wrapping_add(1) & ADDRESS_MASK;
+        }
+    }
+
+    // zx nx zy ny f no control bits, covering every comp of the instruction set
+    fn alu(&self, x: i16, y: i16, control: u16) -> i16 {
+        let mut x = if control & 0b100000 != 0 { 0 } else { x };
+        if control & 0b010000 != 0 {
+            x = !x;
+        }
+        let mut y = if control & 0b001000 != 0 { 0 } else { y };
+        if control & 0b000100 != 0 {
+            y = !y;
+        }
+        let out = if control & 0b000010 != 0 {
+            x.wrapping_add(y)
+        } else {
+            x & y
+        };
+
+        if control & 0b000001 != 0 {
+            !out
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_vm_fixture, translate_program};
+
+    fn translate_fixture(vm_files: &[(&str, &str)], program_name: &str) -> String {
+        translate_program(parse_vm_fixture(vm_files), program_name).expect("Should be valid")
+    }
+
+    #[test]
+    fn run_alu_instructions() {
+        let asm_code = "@7\nD=A\n@3\nD=D-A\n@100\nM=D\nM=M+1\nAM=M-1\nD=!A\n@101\nM=D\nM=-M\nD=D|M";
+        let mut hack_cpu = HackCpu::from_asm(asm_code, "alu.asm").expect("Should be valid");
+        hack_cpu.run(13);
+
+        assert_eq!(4, hack_cpu.ram(100));
+        assert_eq!(5, hack_cpu.ram(101));
+        assert_eq!(-1, hack_cpu.d());
+        assert_eq!(13, hack_cpu.pc());
+    }
+
+    #[test]
+    fn run_jumps() {
+        // sums 1 to 10 into R1
+        let asm_code = "@10\nD=A\n@R0\nM=D\n(LOOP)\n@R0\nD=M\n@END\nD;JEQ\n@R1\nM=D+M\n@R0\nM=M-1\n@LOOP\n0;JMP\n(END)\n@END\n0;JMP";
+        let mut hack_cpu = HackCpu::from_asm(asm_code, "sum.asm").expect("Should be valid");
+        hack_cpu.run(200);
+
+        assert_eq!(55, hack_cpu.ram(1));
+        assert_eq!(0, hack_cpu.ram(0));
+    }
+
+    #[test]
+    fn run_hack_fixture() {
+        let mut hack_cpu = HackCpu::from_hack(include_str!("../SimpleAdd.hack"), "SimpleAdd.hack")
+            .expect("Should be valid");
+        hack_cpu.set_ram(0, 256);
+        hack_cpu.run(60);
+
+        assert_eq!(257, hack_cpu.ram(0));
+        assert_eq!(15, hack_cpu.ram(256));
+    }
+
+    #[test]
+    fn run_translated_fibonacci_element() {
+        let asm_code = translate_fixture(
+            &[
+                ("Sys", include_str!("../08/FibonacciElement/Sys.vm")),
+                ("Main", include_str!("../08/FibonacciElement/Main.vm")),
+            ],
+            "FibonacciElement",
+        );
+        let mut hack_cpu =
+            HackCpu::from_asm(&asm_code, "FibonacciElement.asm").expect("Should be valid");
+        hack_cpu.run(6000);
+
+        assert_eq!(262, hack_cpu.ram(0));
+        assert_eq!(3, hack_cpu.ram(261));
+    }
+
+    #[test]
+    fn load_invalid_hack_code() {
+        assert!(HackCpu::from_hack("0000000000000001\n000000002", "bad.hack").is_err());
+        assert!(HackCpu::from_hack("0000000000000001\n1111111111111111", "ok.hack").is_ok());
+    }
+}
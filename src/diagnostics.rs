@@ -0,0 +1,147 @@
+use crate::TranslateError;
+use std::{error::Error, fmt};
+
+// location of a command in its original vm file, line and column start from 1
+// the whole source line is kept so that errors can be shown with a caret snippet
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Span {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub source_line: String,
+}
+
+impl Span {
+    // rustc style snippet, tabs are expanded so the carets line up
+    pub(crate) fn fmt_snippet(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            // generated code such as the bootstrap has no source to point at
+            return Ok(());
+        }
+
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let caret_offset: usize = self
+            .source_line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum();
+        write!(
+            f,
+            "\n{gutter}--> {self}\n{gutter} |\n{line_number} | {}\n{gutter} | {}{}",
+            self.source_line.replace('\t', "    "),
+            " ".repeat(caret_offset),
+            "^".repeat(self.length.max(1))
+        )
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+// collects every error found in a translation instead of stopping at the first one
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    errors: Vec<TranslateError>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics { errors: Vec::new() }
+    }
+
+    pub fn push(&mut self, error: TranslateError) {
+        self.errors.push(error);
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.errors.extend(other.errors);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, TranslateError> {
+        self.errors.iter()
+    }
+}
+
+impl From<TranslateError> for Diagnostics {
+    fn from(error: TranslateError) -> Diagnostics {
+        Diagnostics {
+            errors: vec![error],
+        }
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = TranslateError;
+    type IntoIter = std::vec::IntoIter<TranslateError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in &self.errors {
+            writeln!(f, "{error}\n")?;
+        }
+
+        match self.errors.len() {
+            1 => write!(f, "error: aborting due to 1 previous error"),
+            count => write!(f, "error: aborting due to {count} previous errors"),
+        }
+    }
+}
+
+impl Error for Diagnostics {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_caret_snippet() {
+        let span = Span {
+            file: String::from("Class1.vm"),
+            line: 12,
+            column: 2,
+            length: 5,
+            source_line: String::from("\tpushy constant 1"),
+        };
+        let error = TranslateError::UnknownCommand {
+            command: String::from("pushy"),
+            span,
+        };
+
+        assert_eq!(
+            "error: unknown command `pushy`\n  --> Class1.vm:12:2\n   |\n12 |     pushy constant 1\n   |     ^^^^^",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn render_all_diagnostics() {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(TranslateError::InvalidArguments(String::from("first")));
+        diagnostics.push(TranslateError::InvalidArguments(String::from("second")));
+
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            "error: first\n\nerror: second\n\nerror: aborting due to 2 previous errors",
+            diagnostics.to_string()
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use crate::interpreter::{STACK_BASE_ADDRESS, STATIC_BASE_ADDRESS};
+use crate::{
+    translate_program_with_options, Diagnostics, HackAssembler, HackCpu, TranslateOptions, VmFile,
+    VmInterpreter,
+};
+use std::fmt;
+
+const TEMP_REGISTERS: std::ops::Range<u16> = 5..13;
+const HEAP_BASE_ADDRESS: u16 = 2048;
+const SCREEN_ADDRESS: u16 = 16384;
+// R13 - R15 are scratch registers of the translated code
+const SCRATCH_REGISTERS: std::ops::Range<u16> = 13..16;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MemoryMismatch {
+    pub address: u16,
+    pub vm_value: i16,
+    pub hack_value: i16,
+}
+
+impl fmt::Display for MemoryMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RAM[{}] is {} in the vm interpreter but {} in the translated hack program",
+            self.address, self.vm_value, self.hack_value
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DifferentialReport {
+    pub vm_steps: usize,
+    pub hack_steps: usize,
+    pub mismatches: Vec<MemoryMismatch>,
+}
+
+// runs a program on the vm interpreter and as translated hack code on the cpu emulator,
+// each until it idles at the end of the program or max_steps, then compares their memory:
+// pointers, temp, statics, the stack below SP and the heap
+// return addresses differ by design between the two so the ones on the stack are skipped,
+// statics are compared by name since the assembler may lay them out in another order,
+// and temp is skipped at -O2 which drops values popped into temp that nothing reads
+pub fn run_differential(
+    vm_files: Vec<VmFile>,
+    program_name: &str,
+    options: &TranslateOptions,
+    initial_ram: &[(u16, i16)],
+    max_steps: usize,
+) -> Result<DifferentialReport, Diagnostics> {
+    let bootstrap_code_exists = options.emits_bootstrap(&vm_files);
+    let mut vm_interpreter = VmInterpreter::new(&vm_files)?;
+    let asm_code = translate_program_with_options(vm_files, program_name, options)?;
+    let (hack_code, symbol_table) =
+        HackAssembler::new().assemble_with_symbols(&asm_code, program_name)?;
+    let mut hack_cpu = HackCpu::from_hack(&hack_code, program_name)?;
+
+    for &(address, value) in initial_ram {
+        vm_interpreter.set_ram(address, value);
+        hack_cpu.set_ram(address, value);
+    }
+    if bootstrap_code_exists {
+        vm_interpreter.bootstrap_with(&options.bootstrap_setup);
+    }
+
+    let mut report = DifferentialReport::default();
+    while report.vm_steps < max_steps && !vm_interpreter.is_idle() {
+        vm_interpreter.step();
+        report.vm_steps += 1;
+    }
+    while report.hack_steps < max_steps && !hack_cpu.is_idle() {
+        hack_cpu.step();
+        report.hack_steps += 1;
+    }
+
+    let return_addresses = return_address_slots(&vm_interpreter);
+    let sp = vm_interpreter.ram(0) as u16;
+    let skips_temp = options.optimization_level >= 2;
+    // (vm address, hack address), the same except for statics
+    let mut compared_addresses: Vec<(u16, u16)> = (0..STATIC_BASE_ADDRESS)
+        .filter(|address| !SCRATCH_REGISTERS.contains(address))
+        .filter(|address| !(skips_temp && TEMP_REGISTERS.contains(address)))
+        .chain(
+            (STACK_BASE_ADDRESS..sp.min(HEAP_BASE_ADDRESS))
+                .filter(|address| !return_addresses.contains(address)),
+        )
+        .chain(HEAP_BASE_ADDRESS..SCREEN_ADDRESS)
+        .map(|address| (address, address))
+        .collect();
+    // a static the translated program never mentions was optimized away with its reads
+    let mut static_addresses: Vec<(u16, u16)> = vm_interpreter
+        .static_addresses()
+        .iter()
+        .filter_map(|((file_name, index), &vm_address)| {
+            let hack_address = symbol_table.get(&format!("{file_name}.{index}"))?;
+            Some((vm_address, *hack_address))
+        })
+        .collect();
+    static_addresses.sort();
+    compared_addresses.extend(static_addresses);
+    report.mismatches = compared_addresses
+        .into_iter()
+        .filter_map(|(vm_address, hack_address)| {
+            let vm_value = vm_interpreter.ram(vm_address);
+            let hack_value = hack_cpu.ram(hack_address);
+            (vm_value != hack_value).then_some(MemoryMismatch {
+                address: vm_address,
+                vm_value,
+                hack_value,
+            })
+        })
+        .collect();
+
+    Ok(report)
+}
+
+// follows the saved LCL of each frame, the return address sits 5 below it
+fn return_address_slots(vm_interpreter: &VmInterpreter) -> Vec<u16> {
+    let mut return_addresses = Vec::new();
+    let mut lcl = vm_interpreter.ram(1) as u16;
+    while (STACK_BASE_ADDRESS + 5..HEAP_BASE_ADDRESS).contains(&lcl)
+        && !return_addresses.contains(&(lcl - 5))
+    {
+        return_addresses.push(lcl - 5);
+        lcl = vm_interpreter.ram(lcl - 4) as u16;
+    }
+
+    return_addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_vm_fixture, read_vm_fixture, Bootstrap, BootstrapSetup};
+    use std::{fs, path::Path};
+
+    const MAX_STEPS: usize = 100_000;
+
+    fn parse_vm_files(paths: &[std::path::PathBuf]) -> Vec<VmFile> {
+        let vm_sources: Vec<(&str, String)> = paths
+            .iter()
+            .map(|path| {
+                let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap();
+                (name, fs::read_to_string(path).expect("Should be valid"))
+            })
+            .collect();
+        parse_vm_fixture(&vm_sources)
+    }
+
+    // checks the default, compact and optimized translations
+    fn assert_no_mismatches(vm_files: Vec<VmFile>, program_name: &str, initial_ram: &[(u16, i16)]) {
+        for (compact, optimization_level) in [0, 1, 2]
+            .into_iter()
+            .flat_map(|level| [(false, level), (true, level)])
+        {
+            let options = TranslateOptions {
+                compact,
+                optimization_level,
+                ..TranslateOptions::default()
+            };
+            let report = run_differential(
+                vm_files.clone(),
+                program_name,
+                &options,
+                initial_ram,
+                MAX_STEPS,
+            )
+            .unwrap_or_else(|diagnostics| panic!("{diagnostics}"));
+            assert!(report.vm_steps < MAX_STEPS, "{program_name} did not finish");
+            assert!(
+                report.hack_steps < MAX_STEPS,
+                "{program_name} did not finish"
+            );
+            assert!(
+                report.mismatches.is_empty(),
+                "{program_name} {options:?}: {:?}",
+                report.mismatches
+            );
+        }
+    }
+
+    #[test]
+    fn differential_program_fixtures() {
+        // initial ram is what each program's *.tst script sets up
+        let fixtures: [(&str, &[(u16, i16)]); 6] = [
+            ("BasicLoop", &[(0, 256), (1, 300), (2, 400), (400, 3)]),
+            (
+                "FibonacciSeries",
+                &[(0, 256), (1, 300), (2, 400), (400, 6), (401, 3000)],
+            ),
+            (
+                "SimpleFunction",
+                &[
+                    (0, 317),
+                    (1, 317),
+                    (2, 310),
+                    (3, 3000),
+                    (4, 4000),
+                    (310, 1234),
+                    (311, 37),
+                    (312, 1000),
+                    (313, 305),
+                    (314, 300),
+                    (315, 3010),
+                    (316, 4010),
+                ],
+            ),
+            ("NestedCall", &[]),
+            ("FibonacciElement", &[]),
+            ("StaticsTest", &[]),
+        ];
+        for (fixture, initial_ram) in fixtures {
+            let vm_files = parse_vm_fixture(&read_vm_fixture(fixture));
+            assert_no_mismatches(vm_files, fixture, initial_ram);
+        }
+    }
+
+    #[test]
+    fn differential_single_file_fixtures() {
+        let pointers: &[(u16, i16)] = &[(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+        for fixture in [
+            "SimpleAdd",
+            "StackTest",
+            "BasicTest",
+            "PointerTest",
+            "StaticTest",
+        ] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("{fixture}.vm"));
+            assert_no_mismatches(parse_vm_files(&[path]), fixture, pointers);
+        }
+    }
+
+    #[test]
+    fn differential_bootstrap_setup() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("08/SimpleFunction/SimpleFunction.vm");
+        let options = TranslateOptions {
+            bootstrap: Bootstrap::Force,
+            bootstrap_setup: BootstrapSetup {
+                entry_function: String::from("SimpleFunction.test"),
+                entry_nargs: 2,
+                sp: 312,
+                lcl: Some(305),
+                arg: Some(300),
+                this: Some(3010),
+                that: Some(4010),
+            },
+            ..TranslateOptions::default()
+        };
+        let report = run_differential(
+            parse_vm_files(&[path]),
+            "SimpleFunction",
+            &options,
+            &[(310, 1234), (311, 37)],
+            MAX_STEPS,
+        )
+        .expect("Should be valid");
+
+        assert!(report.vm_steps < MAX_STEPS && report.hack_steps < MAX_STEPS);
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+    }
+
+    #[test]
+    fn differential_skips_return_addresses() {
+        let report = run_differential(
+            parse_vm_fixture(&[("Main", "push constant 5\npop temp 0")]),
+            "Main",
+            &TranslateOptions::default(),
+            &[(0, 256)],
+            100,
+        )
+        .expect("Should be valid");
+        assert!(report.mismatches.is_empty());
+
+        // a return address slot is skipped, anything else on the stack is compared
+        let mut vm_interpreter = VmInterpreter::new(&[]).expect("Should be valid");
+        vm_interpreter.set_ram(1, 300);
+        vm_interpreter.set_ram(296, 280);
+        assert_eq!(vec![295, 275], return_address_slots(&vm_interpreter));
+    }
+
+    #[test]
+    fn differential_optimized_temp_and_statics() {
+        // -O2 drops both values popped into temp, so temp 3 keeps 0 and Sys.1 never
+        // reaches the assembler, which then puts Sys.2 at 16 rather than 17
+        let vm_files = parse_vm_fixture(&[(
+            "Sys",
+            "function Sys.init 0\npush constant 3000\npop temp 3\npush static 1\npop temp 0\npush constant 7\npop static 2\nlabel END\ngoto END",
+        )]);
+        let options = TranslateOptions {
+            optimization_level: 2,
+            ..TranslateOptions::default()
+        };
+        let report =
+            run_differential(vm_files, "Sys", &options, &[], MAX_STEPS).expect("Should be valid");
+
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+    }
+}
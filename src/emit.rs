@@ -0,0 +1,88 @@
+use crate::VmFile;
+
+// the vm commands of a program as vm code, one file after another
+// this is what gets translated, so after -O2 it shows the optimized commands
+// pushes forwarded into the pop after them only change the generated assembly
+pub fn write_ir(vm_files: &[VmFile]) -> String {
+    let mut ir = String::new();
+    for vm_file in vm_files {
+        ir.push_str(&format!("// {}\n", vm_file.name));
+        for parsed_command in &vm_file.commands {
+            ir.push_str(&format!("{}\n", parsed_command.command));
+        }
+    }
+
+    ir
+}
+
+// the same commands as json with the source line each of them came from
+pub fn write_json(vm_files: &[VmFile], program_name: &str) -> String {
+    let files: Vec<String> = vm_files
+        .iter()
+        .map(|vm_file| {
+            let commands: Vec<String> = vm_file
+                .commands
+                .iter()
+                .map(|parsed_command| {
+                    format!(
+                        "        {{\"line\": {}, \"command\": {}}}",
+                        parsed_command.span.line,
+                        json_string(&parsed_command.command.to_string())
+                    )
+                })
+                .collect();
+            format!(
+                "    {{\n      \"name\": {},\n      \"commands\": [\n{}\n      ]\n    }}",
+                json_string(&vm_file.name),
+                commands.join(",\n")
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"program\": {},\n  \"files\": [\n{}\n  ]\n}}\n",
+        json_string(program_name),
+        files.join(",\n")
+    )
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if u32::from(c) < 0x20 => escaped.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_vm_fixture;
+
+    #[test]
+    fn write_ir_and_json() {
+        let vm_files = parse_vm_fixture(&[
+            ("Main", "// comment\npush constant 7\n\nadd"),
+            ("Sys", "label LOOP"),
+        ]);
+
+        assert_eq!(
+            "// Main\npush constant 7\nadd\n// Sys\nlabel LOOP\n",
+            write_ir(&vm_files)
+        );
+        assert_eq!(
+            "{\n  \"program\": \"Prog\\\"ram\",\n  \"files\": [\n    {\n      \"name\": \"Main\",\n      \"commands\": [\n        {\"line\": 2, \"command\": \"push constant 7\"},\n        {\"line\": 4, \"command\": \"add\"}\n      ]\n    },\n    {\n      \"name\": \"Sys\",\n      \"commands\": [\n        {\"line\": 1, \"command\": \"label LOOP\"}\n      ]\n    }\n  ]\n}\n",
+            write_json(&vm_files, "Prog\"ram")
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{Segment, Span};
+use std::{error::Error, fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum TranslateError {
+    UnknownCommand {
+        command: String,
+        span: Span,
+    },
+    BadSegment {
+        command: String,
+        segment: String,
+        span: Span,
+    },
+    IndexOutOfRange {
+        segment: Segment,
+        index: i32,
+        span: Span,
+    },
+    MalformedInteger {
+        value: String,
+        span: Span,
+    },
+    CountOutOfRange {
+        command: String,
+        count: i32,
+        span: Span,
+    },
+    MissingArgument {
+        command: String,
+        span: Span,
+    },
+    TrailingToken {
+        token: String,
+        span: Span,
+    },
+    UndefinedLabel {
+        label: String,
+        span: Span,
+    },
+    UndefinedFunction {
+        name: String,
+        span: Span,
+    },
+    DuplicateLabel {
+        label: String,
+        span: Span,
+    },
+    DuplicateFunction {
+        name: String,
+        span: Span,
+    },
+    InvalidInstruction {
+        instruction: String,
+        span: Span,
+    },
+    RomOverflow {
+        instructions: usize,
+    },
+    InvalidScriptCommand {
+        command: String,
+        span: Span,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    NoVmFiles {
+        path: PathBuf,
+    },
+    InvalidArguments(String),
+}
+
+impl TranslateError {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            TranslateError::UnknownCommand { span, .. }
+            | TranslateError::BadSegment { span, .. }
+            | TranslateError::IndexOutOfRange { span, .. }
+            | TranslateError::MalformedInteger { span, .. }
+            | TranslateError::CountOutOfRange { span, .. }
+            | TranslateError::MissingArgument { span, .. }
+            | TranslateError::TrailingToken { span, .. }
+            | TranslateError::UndefinedLabel { span, .. }
+            | TranslateError::UndefinedFunction { span, .. }
+            | TranslateError::DuplicateLabel { span, .. }
+            | TranslateError::DuplicateFunction { span, .. }
+            | TranslateError::InvalidInstruction { span, .. }
+            | TranslateError::InvalidScriptCommand { span, .. } => Some(span),
+            TranslateError::RomOverflow { .. }
+            | TranslateError::Io { .. }
+            | TranslateError::NoVmFiles { .. }
+            | TranslateError::InvalidArguments(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslateError::UnknownCommand { command, .. } => {
+                write!(f, "error: unknown command `{command}`")?
+            }
+            TranslateError::BadSegment {
+                command, segment, ..
+            } => write!(f, "error: invalid segment `{segment}` for `{command}`")?,
+            TranslateError::IndexOutOfRange { segment, index, .. } => write!(
+                f,
+                "error: index {index} is out of range for segment `{}`, expected 0 to {}",
+                segment.name(),
+                segment.max_index()
+            )?,
+            TranslateError::MalformedInteger { value, .. } => {
+                write!(f, "error: `{value}` is not a valid integer")?
+            }
+            TranslateError::CountOutOfRange { command, count, .. } => {
+                let counted = if command == "function" {
+                    "locals"
+                } else {
+                    "arguments"
+                };
+                write!(
+                    f,
+                    "error: `{command}` takes 0 to 32767 {counted}, not {count}"
+                )?
+            }
+            TranslateError::MissingArgument { command, .. } => {
+                write!(f, "error: missing argument for `{command}`")?
+            }
+            TranslateError::TrailingToken { token, .. } => write!(
+                f,
+                "error: unexpected token `{token}` after the last argument"
+            )?,
+            TranslateError::UndefinedLabel { label, .. } => {
+                write!(f, "error: undefined label `{label}`")?
+            }
+            TranslateError::UndefinedFunction { name, .. } => {
+                write!(f, "error: undefined function `{name}`")?
+            }
+            TranslateError::DuplicateLabel { label, .. } => {
+                write!(f, "error: duplicate label `{label}`")?
+            }
+            TranslateError::DuplicateFunction { name, .. } => {
+                write!(f, "error: duplicate function `{name}`")?
+            }
+            TranslateError::InvalidInstruction { instruction, .. } => {
+                write!(f, "error: invalid instruction `{instruction}`")?
+            }
+            TranslateError::RomOverflow { instructions } => write!(
+                f,
+                "error: program has {instructions} instructions but the rom only fits 32768"
+            )?,
+            TranslateError::InvalidScriptCommand { command, .. } => {
+                write!(f, "error: invalid test script command `{command}`")?
+            }
+            TranslateError::Io { path, source } => {
+                write!(f, "error: {}: {source}", path.display())?
+            }
+            TranslateError::NoVmFiles { path } => write!(
+                f,
+                "error: Please ensure the file path entered has files of extension type *.vm: {}",
+                path.display()
+            )?,
+            TranslateError::InvalidArguments(message) => write!(f, "error: {message}")?,
+        }
+
+        match self.span() {
+            Some(span) => span.fmt_snippet(f),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for TranslateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TranslateError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use crate::cpu::RAM_SIZE;
+use crate::test_script::ram_address;
+use crate::{
+    validate_vm_files, ArithOp, BootstrapSetup, Diagnostics, ScriptTarget, Segment, TranslateError,
+    VmCommand, VmFile,
+};
+use std::collections::HashMap;
+
+// same memory layout as the translated hack program
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+const THIS: usize = 3;
+const THAT: usize = 4;
+const TEMP_BASE_ADDRESS: usize = 5;
+pub(crate) const STATIC_BASE_ADDRESS: u16 = 16;
+pub(crate) const STACK_BASE_ADDRESS: u16 = 256;
+
+// a command with its jump target, called function or static address already resolved
+struct LoadedCommand {
+    command: VmCommand,
+    target: usize,
+}
+
+// runs vm commands directly on an abstract stack machine, like the nand2tetris vm emulator
+pub struct VmInterpreter {
+    commands: Vec<LoadedCommand>,
+    functions: HashMap<String, usize>,
+    // names of the loaded vm files, without .vm
+    file_names: Vec<String>,
+    // (file name, index) of every static variable and its address
+    static_addresses: HashMap<(String, u16), u16>,
+    ram: Vec<i16>,
+    pc: usize,
+}
+
+impl VmInterpreter {
+    // execution starts at Sys.init when it exists, otherwise at the first command
+    pub fn new(vm_files: &[VmFile]) -> Result<VmInterpreter, Diagnostics> {
+        validate_vm_files(vm_files)?;
+        let mut diagnostics = Diagnostics::new();
+        let mut functions: HashMap<String, usize> = HashMap::new();
+        let mut labels: HashMap<(String, String), usize> = HashMap::new();
+        let mut static_addresses: HashMap<(String, u16), u16> = HashMap::new();
+
+        // labels belong to the function declared before them
+        let mut enclosing_functions: Vec<String> = Vec::new();
+        let mut command_index = 0;
+        for vm_file in vm_files {
+            let mut function_context = String::new();
+            for parsed_command in &vm_file.commands {
+                match &parsed_command.command {
+                    VmCommand::Function { name, .. } => {
+                        function_context = name.clone();
+                        functions.insert(name.clone(), command_index);
+                    }
+                    VmCommand::Label(label_name) => {
+                        labels.insert(
+                            (function_context.clone(), label_name.clone()),
+                            command_index,
+                        );
+                    }
+                    _ => {}
+                }
+                enclosing_functions.push(function_context.clone());
+                command_index += 1;
+            }
+        }
+
+        let mut commands: Vec<LoadedCommand> = Vec::new();
+        for vm_file in vm_files {
+            for parsed_command in &vm_file.commands {
+                let function_context = &enclosing_functions[commands.len()];
+                let target = match &parsed_command.command {
+                    VmCommand::Goto(label_name) | VmCommand::IfGoto(label_name) => labels
+                        .get(&(function_context.clone(), label_name.clone()))
+                        .copied()
+                        .ok_or_else(|| TranslateError::UndefinedLabel {
+                            label: label_name.clone(),
+                            span: parsed_command.span.clone(),
+                        }),
+                    VmCommand::Call { name, .. } => functions.get(name).copied().ok_or_else(|| {
+                        TranslateError::UndefinedFunction {
+                            name: name.clone(),
+                            span: parsed_command.span.clone(),
+                        }
+                    }),
+                    VmCommand::Push {
+                        segment: Segment::Static,
+                        index,
+                    }
+                    | VmCommand::Pop {
+                        segment: Segment::Static,
+                        index,
+                    } => {
+                        // allocated in order of appearance, same as the assembler does
+                        let next_address = STATIC_BASE_ADDRESS + static_addresses.len() as u16;
+                        Ok(usize::from(
+                            *static_addresses
+                                .entry((vm_file.name.clone(), *index))
+                                .or_insert(next_address),
+                        ))
+                    }
+                    _ => Ok(0),
+                };
+
+                match target {
+                    Ok(target) => commands.push(LoadedCommand {
+                        command: parsed_command.command.clone(),
+                        target,
+                    }),
+                    Err(error) => {
+                        diagnostics.push(error);
+                        commands.push(LoadedCommand {
+                            command: parsed_command.command.clone(),
+                            target: 0,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        let pc = functions.get("Sys.init").copied().unwrap_or_default();
+        let mut vm_interpreter = VmInterpreter {
+            commands,
+            functions,
+            file_names: vm_files
+                .iter()
+                .map(|vm_file| vm_file.name.clone())
+                .collect(),
+            static_addresses,
+            ram: vec![0; RAM_SIZE],
+            pc,
+        };
+        vm_interpreter.ram[SP] = STACK_BASE_ADDRESS as i16;
+        Ok(vm_interpreter)
+    }
+
+    // does what the translated bootstrap code does, SP = 256 then call Sys.init 0
+    pub fn bootstrap(&mut self) {
+        self.bootstrap_with(&BootstrapSetup::default());
+    }
+
+    // sets the pointers of the setup then calls its entry function,
+    // returning from it halts the interpreter
+    pub fn bootstrap_with(&mut self, bootstrap_setup: &BootstrapSetup) {
+        let pointers = [
+            (SP, Some(bootstrap_setup.sp)),
+            (LCL, bootstrap_setup.lcl),
+            (ARG, bootstrap_setup.arg),
+            (THIS, bootstrap_setup.this),
+            (THAT, bootstrap_setup.that),
+        ];
+        for (pointer, value) in pointers {
+            if let Some(value) = value {
+                self.ram[pointer] = value as i16;
+            }
+        }
+        if let Some(&entry_function) = self.functions.get(&bootstrap_setup.entry_function) {
+            self.call(
+                entry_function,
+                bootstrap_setup.entry_nargs,
+                self.commands.len(),
+            );
+        }
+    }
+
+    pub fn ram(&self, address: u16) -> i16 {
+        self.ram[usize::from(address) % RAM_SIZE]
+    }
+
+    pub fn set_ram(&mut self, address: u16, value: i16) {
+        self.ram[usize::from(address) % RAM_SIZE] = value;
+    }
+
+    pub(crate) fn static_addresses(&self) -> &HashMap<(String, u16), u16> {
+        &self.static_addresses
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn current_command(&self) -> Option<&VmCommand> {
+        self.commands
+            .get(self.pc)
+            .map(|loaded_command| &loaded_command.command)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.pc >= self.commands.len()
+    }
+
+    // true when halted or stuck in a goto to its own label, which is how Sys.init ends
+    pub fn is_idle(&self) -> bool {
+        let pc = self.skip_labels(self.pc);
+        match self.commands.get(pc) {
+            Some(LoadedCommand {
+                command: VmCommand::Goto(_),
+                target,
+            }) => self.skip_labels(*target) == pc,
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    fn skip_labels(&self, mut pc: usize) -> usize {
+        while self
+            .commands
+            .get(pc)
+            .is_some_and(|loaded_command| matches!(loaded_command.command, VmCommand::Label(_)))
+        {
+            pc += 1;
+        }
+
+        pc
+    }
+
+    // runs up to steps commands, returns how many were run before halting
+    pub fn run(&mut self, steps: usize) -> usize {
+        (0..steps).take_while(|_step| self.step()).count()
+    }
+
+    // executes one vm command, false once the program has run past its last command
+    // labels are passed over without taking a step, same as the vm emulator
+    pub fn step(&mut self) -> bool {
+        self.pc = self.skip_labels(self.pc);
+        let Some(loaded_command) = self.commands.get(self.pc) else {
+            return false;
+        };
+        let target = loaded_command.target;
+        let mut next_pc = self.pc + 1;
+        match loaded_command.command.clone() {
+            VmCommand::Arithmetic(op) => self.arithmetic(op),
+            VmCommand::Push { segment, index } => {
+                let value = match segment {
+                    Segment::Constant => index as i16,
+                    _ => self.ram[self.segment_address(segment, index, target)],
+                };
+                self.push(value);
+            }
+            VmCommand::Pop { segment, index } => {
+                let value = self.pop();
+                let address = self.segment_address(segment, index, target);
+                self.ram[address] = value;
+            }
+            VmCommand::Label(_) => {}
+            VmCommand::Goto(_) => next_pc = target,
+            VmCommand::IfGoto(_) => {
+                if self.pop() != 0 {
+                    next_pc = target;
+                }
+            }
+            VmCommand::Function { nlocals, .. } => {
+                for _local in 0..nlocals {
+                    self.push(0);
+                }
+            }
+            VmCommand::Call { nargs, .. } => {
+                self.call(target, nargs, next_pc);
+                next_pc = target;
+            }
+            VmCommand::Return => {
+                let frame = self.ram[LCL] as u16;
+                let return_address = self.ram(frame.wrapping_sub(5));
+                let return_value = self.pop();
+                let arg = self.ram[ARG] as u16;
+                self.set_ram(arg, return_value);
+                self.ram[SP] = arg.wrapping_add(1) as i16;
+                self.ram[THAT] = self.ram(frame.wrapping_sub(1));
+                self.ram[THIS] = self.ram(frame.wrapping_sub(2));
+                self.ram[ARG] = self.ram(frame.wrapping_sub(3));
+                self.ram[LCL] = self.ram(frame.wrapping_sub(4));
+                next_pc = return_address as u16 as usize;
+            }
+        }
+
+        self.pc = next_pc;
+        true
+    }
+
+    fn call(&mut self, function_index: usize, nargs: u16, return_address: usize) {
+        self.push(return_address as i16);
+        self.push(self.ram[LCL]);
+        self.push(self.ram[ARG]);
+        self.push(self.ram[THIS]);
+        self.push(self.ram[THAT]);
+        self.ram[ARG] = self.ram[SP].wrapping_sub(5 + nargs as i16);
+        self.ram[LCL] = self.ram[SP];
+        self.pc = function_index;
+    }
+
+    fn segment_address(&self, segment: Segment, index: u16, static_address: usize) -> usize {
+        let address = match segment {
+            Segment::Local => self.ram[LCL] as u16 as usize + usize::from(index),
+            Segment::Argument => self.ram[ARG] as u16 as usize + usize::from(index),
+            Segment::This => self.ram[THIS] as u16 as usize + usize::from(index),
+            Segment::That => self.ram[THAT] as u16 as usize + usize::from(index),
+            Segment::Pointer => THIS + usize::from(index),
+            Segment::Temp => TEMP_BASE_ADDRESS + usize::from(index),
+            Segment::Static => static_address,
+            Segment::Constant => 0,
+        };
+
+        address % RAM_SIZE
+    }
+
+    fn push(&mut self, value: i16) {
+        let sp = self.ram[SP] as u16;
+        self.set_ram(sp, value);
+        self.ram[SP] = sp.wrapping_add(1) as i16;
+    }
+
+    fn pop(&mut self) -> i16 {
+        let sp = (self.ram[SP] as u16).wrapping_sub(1);
+        self.ram[SP] = sp as i16;
+        self.ram(sp)
+    }
+
+    fn arithmetic(&mut self, op: ArithOp) {
+        let y = self.pop();
+        let x = if op.is_unary() { 0 } else { self.pop() };
+        self.push(op.apply(x, y));
+    }
+
+    // argument[2] to the ram address of argument 2
+    fn segment_variable_address(&self, name: &str) -> Option<u16> {
+        let (segment, index) = name.strip_suffix(']')?.split_once('[')?;
+        let index = index.parse::<u16>().ok()?;
+        let base_address = match segment {
+            "local" => self.ram[LCL],
+            "argument" => self.ram[ARG],
+            "this" => self.ram[THIS],
+            "that" => self.ram[THAT],
+            "temp" => TEMP_BASE_ADDRESS as i16,
+            _ => return None,
+        };
+        Some((base_address as u16).wrapping_add(index))
+    }
+
+    fn pointer_address(name: &str) -> Option<usize> {
+        match name {
+            "sp" => Some(SP),
+            "local" => Some(LCL),
+            "argument" => Some(ARG),
+            "this" => Some(THIS),
+            "that" => Some(THAT),
+            _ => None,
+        }
+    }
+}
+
+// drives the *VME.tst scripts, which step with vmstep
+impl ScriptTarget for VmInterpreter {
+    fn step(&mut self, step_command: &str) -> bool {
+        match step_command {
+            "vmstep" => {
+                VmInterpreter::step(self);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<i16> {
+        let address = VmInterpreter::pointer_address(name)
+            .map(|address| address as u16)
+            .or_else(|| ram_address(name))
+            .or_else(|| self.segment_variable_address(name))?;
+        Some(self.ram(address))
+    }
+
+    fn set_variable(&mut self, name: &str, value: i16) -> bool {
+        let address = VmInterpreter::pointer_address(name)
+            .map(|address| address as u16)
+            .or_else(|| ram_address(name))
+            .or_else(|| self.segment_variable_address(name));
+        match address {
+            Some(address) => {
+                self.set_ram(address, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn load(&mut self, file_name: Option<&str>) -> bool {
+        match file_name {
+            Some(file_name) => file_name
+                .strip_suffix(".vm")
+                .is_some_and(|name| self.file_names.iter().any(|loaded| loaded == name)),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_vm_fixture, read_vm_fixture, run_test_script};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn run_fixture_vme_test_scripts() {
+        let fixtures = [
+            "BasicLoop",
+            "FibonacciSeries",
+            "SimpleFunction",
+            "NestedCall",
+            "FibonacciElement",
+            "StaticsTest",
+        ];
+        for fixture in fixtures {
+            let directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("08")
+                .join(fixture);
+            let vm_files = parse_vm_fixture(&read_vm_fixture(fixture));
+            let mut vm_interpreter = VmInterpreter::new(&vm_files).expect("Should be valid");
+
+            let script_path = directory.join(format!("{fixture}VME.tst"));
+            let script = fs::read_to_string(&script_path).expect("Should be valid");
+            let script_output = run_test_script(&script, fixture, &mut vm_interpreter)
+                .unwrap_or_else(|diagnostics| panic!("{diagnostics}"));
+            let compare_to = script_output.compare_to.clone().expect("Should be set");
+            let cmp_code = fs::read_to_string(directory.join(compare_to)).expect("Should be valid");
+            let mismatches = script_output.compare(&cmp_code);
+            assert!(mismatches.is_empty(), "{fixture}: {mismatches:?}");
+            assert!(run_test_script("load Other.vm;", fixture, &mut vm_interpreter).is_err());
+        }
+    }
+
+    #[test]
+    fn run_bootstrapped_program() {
+        let vm_files = parse_vm_fixture(&[
+            (
+                "Sys",
+                "function Sys.init 0\npush constant 7\npush constant 21\ncall Main.double 1\npop static 0\nlabel END\ngoto END",
+            ),
+            (
+                "Main",
+                "function Main.double 1\npush argument 0\npop local 0\npush local 0\npush local 0\nadd\nreturn",
+            ),
+        ]);
+        let mut vm_interpreter = VmInterpreter::new(&vm_files).expect("Should be valid");
+        vm_interpreter.bootstrap();
+        vm_interpreter.run(100);
+
+        // stack holds 7 above the Sys.init frame, static 0 holds the doubled value
+        assert_eq!(262, vm_interpreter.ram(0));
+        assert_eq!(7, vm_interpreter.ram(261));
+        assert_eq!(42, vm_interpreter.ram(16));
+        assert!(!vm_interpreter.is_halted());
+    }
+
+    #[test]
+    fn reject_undefined_targets() {
+        let vm_files = parse_vm_fixture(&[(
+            "Main",
+            "function Main.main 0\nlabel LOOP\ngoto LOOP\nfunction Main.other 0\ngoto LOOP\ncall Main.missing 0",
+        )]);
+        let diagnostics = match VmInterpreter::new(&vm_files) {
+            Ok(_) => panic!("Should be invalid"),
+            Err(diagnostics) => diagnostics,
+        };
+        let lines: Vec<usize> = diagnostics
+            .iter()
+            .filter_map(|error| error.span())
+            .map(|span| span.line)
+            .collect();
+
+        assert_eq!(vec![5, 6], lines);
+    }
+}
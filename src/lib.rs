@@ -1,4 +1,33 @@
-use std::{collections::HashMap, error::Error};
+mod assembler;
+mod call_graph;
+mod cpu;
+mod diagnostics;
+mod differential;
+mod emit;
+mod error;
+mod interpreter;
+mod linker;
+mod peephole;
+mod stream;
+mod test_script;
+mod validate;
+mod vm_optimizer;
+
+pub use assembler::HackAssembler;
+pub use call_graph::{CallEdge, CallGraph, FunctionNode};
+pub use cpu::HackCpu;
+pub use diagnostics::{Diagnostics, Span};
+pub use differential::{run_differential, DifferentialReport, MemoryMismatch};
+pub use emit::{write_ir, write_json};
+pub use error::TranslateError;
+pub use interpreter::VmInterpreter;
+pub use linker::{link_vm_files, LinkReport, UnreachableLabel};
+pub use peephole::optimize_asm;
+use std::{collections::HashMap, fmt};
+pub use stream::StreamTranslator;
+pub use test_script::{run_test_script, Mismatch, ScriptOutput, ScriptTarget};
+pub use validate::validate_vm_files;
+pub use vm_optimizer::{optimize_vm_files, remove_unreachable_functions};
 
 #[derive(Eq, Hash, PartialEq)]
 pub enum VMCommandType {
@@ -13,6 +42,223 @@ pub enum VMCommandType {
     Ccall,
 }
 
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Segment {
+    Constant,
+    Local,
+    Argument,
+    This,
+    That,
+    Static,
+    Temp,
+    Pointer,
+}
+
+impl Segment {
+    pub fn from_name(name: &str) -> Option<Segment> {
+        match name {
+            "constant" => Some(Segment::Constant),
+            "local" => Some(Segment::Local),
+            "argument" => Some(Segment::Argument),
+            "this" => Some(Segment::This),
+            "that" => Some(Segment::That),
+            "static" => Some(Segment::Static),
+            "temp" => Some(Segment::Temp),
+            "pointer" => Some(Segment::Pointer),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Segment::Constant => "constant",
+            Segment::Local => "local",
+            Segment::Argument => "argument",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Static => "static",
+            Segment::Temp => "temp",
+            Segment::Pointer => "pointer",
+        }
+    }
+
+    // temp is R5 - R12 and pointer is THIS and THAT, every other segment is only
+    // bounded by the largest constant
+    pub fn max_index(&self) -> u16 {
+        match self {
+            Segment::Temp => 7,
+            Segment::Pointer => 1,
+            Segment::Constant
+            | Segment::Local
+            | Segment::Argument
+            | Segment::This
+            | Segment::That
+            | Segment::Static => i16::MAX as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+impl ArithOp {
+    pub fn from_name(name: &str) -> Option<ArithOp> {
+        match name {
+            "add" => Some(ArithOp::Add),
+            "sub" => Some(ArithOp::Sub),
+            "neg" => Some(ArithOp::Neg),
+            "eq" => Some(ArithOp::Eq),
+            "gt" => Some(ArithOp::Gt),
+            "lt" => Some(ArithOp::Lt),
+            "and" => Some(ArithOp::And),
+            "or" => Some(ArithOp::Or),
+            "not" => Some(ArithOp::Not),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArithOp::Add => "add",
+            ArithOp::Sub => "sub",
+            ArithOp::Neg => "neg",
+            ArithOp::Eq => "eq",
+            ArithOp::Gt => "gt",
+            ArithOp::Lt => "lt",
+            ArithOp::And => "and",
+            ArithOp::Or => "or",
+            ArithOp::Not => "not",
+        }
+    }
+
+    // neg and not take only y off the stack
+    pub fn is_unary(&self) -> bool {
+        matches!(self, ArithOp::Neg | ArithOp::Not)
+    }
+
+    // the 16 bit result of x op y, true is -1 and false is 0
+    pub fn apply(&self, x: i16, y: i16) -> i16 {
+        match self {
+            ArithOp::Add => x.wrapping_add(y),
+            ArithOp::Sub => x.wrapping_sub(y),
+            ArithOp::Neg => y.wrapping_neg(),
+            // comparisons are on the difference, same as the translated code
+            ArithOp::Eq => -i16::from(x.wrapping_sub(y) == 0),
+            ArithOp::Gt => -i16::from(x.wrapping_sub(y) > 0),
+            ArithOp::Lt => -i16::from(x.wrapping_sub(y) < 0),
+            ArithOp::And => x & y,
+            ArithOp::Or => x | y,
+            ArithOp::Not => !y,
+        }
+    }
+}
+
+// a single parsed vm command, displaying it gives back the vm source line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VmCommand {
+    Arithmetic(ArithOp),
+    Push { segment: Segment, index: u16 },
+    Pop { segment: Segment, index: u16 },
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function { name: String, nlocals: u16 },
+    Call { name: String, nargs: u16 },
+    Return,
+}
+
+impl VmCommand {
+    pub fn command_type(&self) -> VMCommandType {
+        match self {
+            VmCommand::Arithmetic(_) => VMCommandType::Carithmetic,
+            VmCommand::Push { .. } => VMCommandType::Cpush,
+            VmCommand::Pop { .. } => VMCommandType::Cpop,
+            VmCommand::Label(_) => VMCommandType::Clabel,
+            VmCommand::Goto(_) => VMCommandType::Cgoto,
+            VmCommand::IfGoto(_) => VMCommandType::Cif,
+            VmCommand::Function { .. } => VMCommandType::Cfunction,
+            VmCommand::Call { .. } => VMCommandType::Ccall,
+            VmCommand::Return => VMCommandType::Creturn,
+        }
+    }
+}
+
+impl fmt::Display for VmCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmCommand::Arithmetic(op) => write!(f, "{}", op.name()),
+            VmCommand::Push { segment, index } => write!(f, "push {} {index}", segment.name()),
+            VmCommand::Pop { segment, index } => write!(f, "pop {} {index}", segment.name()),
+            VmCommand::Label(label_name) => write!(f, "label {label_name}"),
+            VmCommand::Goto(label_name) => write!(f, "goto {label_name}"),
+            VmCommand::IfGoto(label_name) => write!(f, "if-goto {label_name}"),
+            VmCommand::Function { name, nlocals } => write!(f, "function {name} {nlocals}"),
+            VmCommand::Call { name, nargs } => write!(f, "call {name} {nargs}"),
+            VmCommand::Return => write!(f, "return"),
+        }
+    }
+}
+
+// a vm command together with where it was written
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParsedCommand {
+    pub command: VmCommand,
+    pub span: Span,
+}
+
+impl ParsedCommand {
+    pub fn new(command: VmCommand, span: Span) -> ParsedCommand {
+        ParsedCommand { command, span }
+    }
+}
+
+// a parsed vm file, its name is the file stem which static variables are namespaced by
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VmFile {
+    pub name: String,
+    pub commands: Vec<ParsedCommand>,
+}
+
+impl VmFile {
+    pub fn new(name: &str, commands: Vec<ParsedCommand>) -> VmFile {
+        VmFile {
+            name: name.to_string(),
+            commands,
+        }
+    }
+}
+
+pub fn get_command_symbol_table() -> HashMap<VMCommandType, Vec<&'static str>> {
+    let mut command_symbol_table: HashMap<VMCommandType, Vec<&str>> = HashMap::new();
+    command_symbol_table.insert(
+        VMCommandType::Carithmetic,
+        vec!["add", "sub", "neg", "eq", "gt", "lt", "and", "or", "not"],
+    );
+    command_symbol_table.insert(
+        VMCommandType::Cpush,
+        vec![
+            "constant", "local", "argument", "this", "that", "static", "temp", "pointer",
+        ],
+    );
+    command_symbol_table.insert(
+        VMCommandType::Cpop,
+        vec![
+            "local", "argument", "this", "that", "static", "temp", "pointer",
+        ],
+    );
+    command_symbol_table
+}
+
 pub struct VmCodeParser;
 
 impl Default for VmCodeParser {
@@ -26,7 +272,9 @@ impl VmCodeParser {
         VmCodeParser
     }
 
-    pub fn clean_vm_code(&self, vm_code: String) -> String {
+    // parse_line skips comments itself, the tests still use this to compare against
+    #[cfg(test)]
+    fn clean_vm_code(&self, vm_code: String) -> String {
         let mut cleaned_vm_code = String::from("");
         const COMMENTS: &str = "//";
         for current_line in vm_code.lines() {
@@ -47,62 +295,216 @@ impl VmCodeParser {
 
         // remove last \n
         cleaned_vm_code.pop();
-        //dbg!(&cleaned_instructions);
         cleaned_vm_code
     }
 
+    pub fn parse(
+        &self,
+        vm_code: &str,
+        file_name: &str,
+        command_table: &HashMap<VMCommandType, Vec<&str>>,
+    ) -> Result<Vec<ParsedCommand>, Diagnostics> {
+        let mut parsed_commands = Vec::new();
+        let mut diagnostics = Diagnostics::new();
+        for (line_index, current_line) in vm_code.lines().enumerate() {
+            match self.parse_line(current_line, line_index + 1, file_name, command_table) {
+                Ok(Some(parsed_command)) => parsed_commands.push(parsed_command),
+                Ok(None) => {}
+                Err(error) => diagnostics.push(error),
+            }
+        }
+
+        if diagnostics.is_empty() {
+            Ok(parsed_commands)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    // parses a single line without its line ending, blank and comment lines give None
+    pub fn parse_line(
+        &self,
+        current_line: &str,
+        line_number: usize,
+        file_name: &str,
+        command_table: &HashMap<VMCommandType, Vec<&str>>,
+    ) -> Result<Option<ParsedCommand>, TranslateError> {
+        const COMMENTS: &str = "//";
+        let vm_code_before_comment = match current_line.find(COMMENTS) {
+            Some(comment_start) => &current_line[..comment_start],
+            None => current_line,
+        };
+        let current_command = vm_code_before_comment.trim();
+        if current_command.is_empty() {
+            return Ok(None);
+        }
+
+        let leading_whitespace =
+            vm_code_before_comment.len() - vm_code_before_comment.trim_start().len();
+        let span = Span {
+            file: file_name.to_string(),
+            line: line_number,
+            column: current_line[..leading_whitespace].chars().count() + 1,
+            length: current_command.chars().count(),
+            source_line: current_line.to_string(),
+        };
+        let command = self.parse_command(current_command, &span, command_table)?;
+
+        Ok(Some(ParsedCommand::new(command, span)))
+    }
+
+    fn parse_command(
+        &self,
+        current_command: &str,
+        span: &Span,
+        command_table: &HashMap<VMCommandType, Vec<&str>>,
+    ) -> Result<VmCommand, TranslateError> {
+        let mut tokens = self.tokens(current_command);
+        let keyword = tokens.next().unwrap_or_default();
+        let missing_argument = || TranslateError::MissingArgument {
+            command: keyword.to_string(),
+            span: span.clone(),
+        };
+        let Some(command_type) = self.command_type(current_command, command_table) else {
+            // push and pop are known commands, so it's the segment that is wrong
+            return Err(match (keyword, tokens.next()) {
+                ("push" | "pop", Some(segment)) => TranslateError::BadSegment {
+                    command: keyword.to_string(),
+                    segment: segment.to_string(),
+                    span: span.clone(),
+                },
+                ("push" | "pop", None) => missing_argument(),
+                _ => TranslateError::UnknownCommand {
+                    command: keyword.to_string(),
+                    span: span.clone(),
+                },
+            });
+        };
+
+        // every command takes a fixed number of arguments, anything after them is an error
+        let argument_count = match command_type {
+            VMCommandType::Carithmetic | VMCommandType::Creturn => 0,
+            VMCommandType::Clabel | VMCommandType::Cgoto | VMCommandType::Cif => 1,
+            VMCommandType::Cpush
+            | VMCommandType::Cpop
+            | VMCommandType::Cfunction
+            | VMCommandType::Ccall => 2,
+        };
+        if let Some(token) = tokens.nth(argument_count) {
+            return Err(TranslateError::TrailingToken {
+                token: token.to_string(),
+                span: self.token_span(span, current_command, token),
+            });
+        }
+
+        let arg1 = self.arg1(current_command, &command_type);
+        let arg2 = || {
+            let value = self
+                .arg2(current_command, &command_type)
+                .ok_or_else(missing_argument)?;
+            value
+                .parse::<i32>()
+                .map_err(|_| TranslateError::MalformedInteger {
+                    value: value.to_string(),
+                    span: span.clone(),
+                })
+        };
+        let vm_command = match command_type {
+            VMCommandType::Carithmetic => ArithOp::from_name(keyword)
+                .map(VmCommand::Arithmetic)
+                .ok_or_else(|| TranslateError::UnknownCommand {
+                    command: keyword.to_string(),
+                    span: span.clone(),
+                })?,
+            VMCommandType::Cpush | VMCommandType::Cpop => {
+                let segment_value = arg1.ok_or_else(missing_argument)?;
+                let segment = Segment::from_name(segment_value).ok_or_else(|| {
+                    TranslateError::BadSegment {
+                        command: keyword.to_string(),
+                        segment: segment_value.to_string(),
+                        span: span.clone(),
+                    }
+                })?;
+                let index = validate::check_index(keyword, segment, arg2()?, span)?;
+                if command_type == VMCommandType::Cpush {
+                    VmCommand::Push { segment, index }
+                } else {
+                    VmCommand::Pop { segment, index }
+                }
+            }
+            VMCommandType::Clabel => {
+                VmCommand::Label(arg1.ok_or_else(missing_argument)?.to_string())
+            }
+            VMCommandType::Cgoto => VmCommand::Goto(arg1.ok_or_else(missing_argument)?.to_string()),
+            VMCommandType::Cif => VmCommand::IfGoto(arg1.ok_or_else(missing_argument)?.to_string()),
+            VMCommandType::Cfunction | VMCommandType::Ccall => {
+                let name = arg1.ok_or_else(missing_argument)?.to_string();
+                let count = validate::check_count(keyword, arg2()?, span)?;
+                if command_type == VMCommandType::Cfunction {
+                    VmCommand::Function {
+                        name,
+                        nlocals: count,
+                    }
+                } else {
+                    VmCommand::Call { name, nargs: count }
+                }
+            }
+            VMCommandType::Creturn => VmCommand::Return,
+        };
+
+        Ok(vm_command)
+    }
+
+    // the words of a command, separated by any amount of unicode whitespace such as tabs
+    fn tokens<'a>(&self, current_command: &'a str) -> std::str::SplitWhitespace<'a> {
+        current_command.split_whitespace()
+    }
+
+    // narrows the span of a whole command down to one of its tokens
+    fn token_span(&self, span: &Span, current_command: &str, token: &str) -> Span {
+        // tokens are slices of the command, so their distance is the byte offset
+        let token_offset = token.as_ptr() as usize - current_command.as_ptr() as usize;
+        Span {
+            column: span.column + current_command[..token_offset].chars().count(),
+            length: token.chars().count(),
+            ..span.clone()
+        }
+    }
+
+    // keywords only match as whole words, pushy or pushconstant are not push
     fn command_type(
         &self,
         current_command: &str,
         command_table: &HashMap<VMCommandType, Vec<&str>>,
     ) -> Option<VMCommandType> {
-        if current_command.starts_with("push") {
-            let push_command_vec: &Vec<&str> = command_table
-                .get(&VMCommandType::Cpush)
-                .expect("Did not initialize in function");
-            if push_command_vec.iter().any(|command| {
-                self.arg1(current_command, &VMCommandType::Cpush)
-                    .is_some_and(|value| value == *command)
-            }) {
-                Some(VMCommandType::Cpush)
-            } else {
-                None
+        let keyword = self.tokens(current_command).next()?;
+        match keyword {
+            "push" | "pop" => {
+                let command_type = if keyword == "push" {
+                    VMCommandType::Cpush
+                } else {
+                    VMCommandType::Cpop
+                };
+                let segment_vec: &Vec<&str> = command_table
+                    .get(&command_type)
+                    .expect("Did not initialize in function");
+                self.arg1(current_command, &command_type)
+                    .filter(|segment| segment_vec.contains(segment))
+                    .map(|_| command_type)
             }
-        } else if current_command.starts_with("pop") {
-            let pop_command_vec: &Vec<&str> = command_table
-                .get(&VMCommandType::Cpop)
-                .expect("Did not initialize in function");
-            if pop_command_vec.iter().any(|command| {
-                self.arg1(current_command, &VMCommandType::Cpop)
-                    .is_some_and(|value| value == *command)
-            }) {
-                Some(VMCommandType::Cpop)
-            } else {
-                None
-            }
-        } else if current_command.starts_with("label") {
-            Some(VMCommandType::Clabel)
-        } else if current_command.starts_with("goto") {
-            Some(VMCommandType::Cgoto)
-        } else if current_command.starts_with("if-goto") {
-            Some(VMCommandType::Cif)
-        } else if current_command.starts_with("call") {
-            Some(VMCommandType::Ccall)
-        } else if current_command.starts_with("function") {
-            Some(VMCommandType::Cfunction)
-        } else if current_command == "return" {
-            Some(VMCommandType::Creturn)
-        } else {
-            let arithmetic_command_vec: &Vec<&str> = command_table
-                .get(&VMCommandType::Carithmetic)
-                .expect("Did not initialize in function");
-            if arithmetic_command_vec
-                .iter()
-                .any(|command| current_command == *command)
-            {
-                Some(VMCommandType::Carithmetic)
-            } else {
-                None
+            "label" => Some(VMCommandType::Clabel),
+            "goto" => Some(VMCommandType::Cgoto),
+            "if-goto" => Some(VMCommandType::Cif),
+            "call" => Some(VMCommandType::Ccall),
+            "function" => Some(VMCommandType::Cfunction),
+            "return" => Some(VMCommandType::Creturn),
+            _ => {
+                let arithmetic_command_vec: &Vec<&str> = command_table
+                    .get(&VMCommandType::Carithmetic)
+                    .expect("Did not initialize in function");
+                arithmetic_command_vec
+                    .contains(&keyword)
+                    .then_some(VMCommandType::Carithmetic)
             }
         }
     }
@@ -116,7 +518,7 @@ impl VmCodeParser {
             | VMCommandType::Cgoto
             | VMCommandType::Cif
             | VMCommandType::Cfunction
-            | VMCommandType::Ccall => current_command.split(" ").nth(1),
+            | VMCommandType::Ccall => self.tokens(current_command).nth(1),
         }
     }
 
@@ -130,301 +532,435 @@ impl VmCodeParser {
             VMCommandType::Cpush
             | VMCommandType::Cpop
             | VMCommandType::Ccall
-            | VMCommandType::Cfunction => current_command
-                .split(" ")
-                .nth(2)
-                .filter(|&maybe_index| maybe_index.parse::<i16>().is_ok()),
+            | VMCommandType::Cfunction => self.tokens(current_command).nth(2),
         }
     }
 }
 
+// whether the program starts with bootstrap code calling Sys.init
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Bootstrap {
+    // only when one of the files is named Sys
+    #[default]
+    Auto,
+    Force,
+    Suppress,
+}
+
+// what the bootstrap code sets up before calling the entry function,
+// pointers left as None keep whatever the ram held when the program started
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BootstrapSetup {
+    pub entry_function: String,
+    // arguments already on the stack below the initial SP
+    pub entry_nargs: u16,
+    pub sp: u16,
+    pub lcl: Option<u16>,
+    pub arg: Option<u16>,
+    pub this: Option<u16>,
+    pub that: Option<u16>,
+}
+
+impl Default for BootstrapSetup {
+    fn default() -> Self {
+        BootstrapSetup {
+            entry_function: String::from("Sys.init"),
+            entry_nargs: 0,
+            sp: 256,
+            lcl: None,
+            arg: None,
+            this: None,
+            that: None,
+        }
+    }
+}
+
+// code generation settings shared by every writer of one translation
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TranslateOptions {
+    pub bootstrap: Bootstrap,
+    pub bootstrap_setup: BootstrapSetup,
+    // comparisons, calls and returns jump to shared $$CMP, $$CALL and $$RETURN routines
+    // instead of being inlined, trading a few cycles for far fewer instructions
+    pub compact: bool,
+    // 0 translates every command as written, 1 runs the peephole optimizer and
+    // 2 also folds constants and forwards pushes straight into the following pop,
+    // dropping pops into temp registers nothing reads
+    pub optimization_level: u8,
+    // leaves out every function that can not be reached from root_functions
+    // or from the entry function of the bootstrap
+    pub remove_unreachable_functions: bool,
+    pub root_functions: Vec<String>,
+}
+
+impl TranslateOptions {
+    // programs without bootstrap code end in an endless loop instead
+    pub fn emits_bootstrap(&self, vm_files: &[VmFile]) -> bool {
+        self.emits_bootstrap_for(vm_files.iter().map(|vm_file| vm_file.name.as_str()))
+    }
+
+    // the functions remove_unreachable_functions starts from, None when nothing is removed
+    // the entry function of the bootstrap is always one of them, without bootstrap code
+    // or roots the program starts with whatever comes first, so every function is kept
+    pub fn reachability_roots(
+        &self,
+        vm_files: &[VmFile],
+    ) -> Result<Option<Vec<String>>, Diagnostics> {
+        if !self.remove_unreachable_functions {
+            return Ok(None);
+        }
+
+        let mut diagnostics = Diagnostics::new();
+        let call_graph = CallGraph::new(vm_files);
+        for root_function in &self.root_functions {
+            let declared = call_graph
+                .function(root_function)
+                .is_some_and(|function| function.file.is_some());
+            if !declared {
+                diagnostics.push(TranslateError::UndefinedFunction {
+                    name: root_function.clone(),
+                    span: Span::default(),
+                });
+            }
+        }
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        let mut roots = self.root_functions.clone();
+        if self.emits_bootstrap(vm_files) {
+            roots.push(self.bootstrap_setup.entry_function.clone());
+        }
+        Ok(if roots.is_empty() { None } else { Some(roots) })
+    }
+
+    // the same decision from the file names alone, before any file is parsed
+    pub fn emits_bootstrap_for<'a>(&self, mut file_names: impl Iterator<Item = &'a str>) -> bool {
+        match self.bootstrap {
+            Bootstrap::Auto => file_names.any(|file_name| file_name == "Sys"),
+            Bootstrap::Force => true,
+            Bootstrap::Suppress => false,
+        }
+    }
+}
+
+// hands out label numbers that are unique across all files of one translation
+pub struct LabelAllocator {
+    next_label: usize,
+    // return addresses of calls outside of any function are counted on their own
+    next_return_label: usize,
+}
+
+impl Default for LabelAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LabelAllocator {
+    pub fn new() -> LabelAllocator {
+        LabelAllocator {
+            next_label: 0,
+            next_return_label: 0,
+        }
+    }
+
+    pub fn allocate(&mut self) -> usize {
+        let label = self.next_label;
+        self.next_label += 1;
+        label
+    }
+
+    pub fn allocate_return(&mut self) -> usize {
+        let label = self.next_return_label;
+        self.next_return_label += 1;
+        label
+    }
+}
+
+// what the writer keeps track of from one command of a file to the next
+#[derive(Debug, Default)]
+pub(crate) struct WriterState {
+    // labels are scoped by the function whose body is being translated
+    current_function: String,
+    // return addresses are numbered per function, Foo.bar$ret.0, Foo.bar$ret.1, ...
+    call_count: usize,
+}
+
 pub struct VmCodeWriter {
-    code_parser: VmCodeParser,
-    cleaned_vm_commands: String,
+    vm_commands: Vec<ParsedCommand>,
+    options: TranslateOptions,
 }
 
 impl VmCodeWriter {
-    pub fn new(code_parser: VmCodeParser, cleaned_vm_commands: String) -> VmCodeWriter {
+    pub fn new(vm_commands: Vec<ParsedCommand>) -> VmCodeWriter {
+        VmCodeWriter::with_options(vm_commands, TranslateOptions::default())
+    }
+
+    pub fn with_options(
+        vm_commands: Vec<ParsedCommand>,
+        options: TranslateOptions,
+    ) -> VmCodeWriter {
         VmCodeWriter {
-            code_parser,
-            cleaned_vm_commands,
+            vm_commands,
+            options,
         }
     }
 
     pub fn translate(
         &self,
-        command_table: &HashMap<VMCommandType, Vec<&str>>,
         file_name: &str,
-        function_call_stack: &mut Vec<String>,
-    ) -> Result<String, Box<dyn Error>> {
+        label_allocator: &mut LabelAllocator,
+    ) -> Result<String, Diagnostics> {
         let mut translated_vm_code = String::from("");
-        let mut line_number: i16 = 0;
-        for current_command in self.cleaned_vm_commands.lines() {
-            if let Some(command_type) = self
-                .code_parser
-                .command_type(current_command, command_table)
-            {
-                let segment_list = command_table.get(&command_type);
-                match command_type {
-                    VMCommandType::Carithmetic => {
-                        // arg functions kinda useless as it just returns itself
-                        if let Some(translated_command) = self.write_arithmetic(
-                            current_command,
-                            segment_list.expect("Did not intialize in symbol table"),
-                            &line_number,
-                        ) {
-                            translated_vm_code.push_str(&translated_command);
-                            translated_vm_code.push('\n');
-                        } else {
-                            Err(format!(
-                                "Command translation failed for current command: {current_command}"
-                            ))?
-                        }
-                    }
-                    VMCommandType::Cpush | VMCommandType::Cpop => {
-                        let segment = self.code_parser.arg1(current_command, &command_type);
-                        let index = self.code_parser.arg2(current_command, &command_type);
-                        if let (Some(segment_value), Some(index_value)) = (segment, index) {
-                            if command_type == VMCommandType::Cpush {
-                                if let Some(translated_command) = self.write_push(
-                                    segment_value,
-                                    index_value,
-                                    segment_list.expect("Did not initialize in symbol table"),
-                                    file_name,
-                                ) {
-                                    translated_vm_code.push_str(&translated_command);
-                                    translated_vm_code.push('\n');
-                                } else {
-                                    Err(format!("Command translation failed for current command: {current_command}"))?
-                                }
-                            } else if command_type == VMCommandType::Cpop {
-                                if let Some(translated_command) = self.write_pop(
-                                    segment_value,
-                                    index_value,
-                                    segment_list.expect("Did not initialize in symbol table"),
-                                    file_name,
-                                ) {
-                                    translated_vm_code.push_str(&translated_command);
-                                    translated_vm_code.push('\n');
-                                } else {
-                                    Err(format!("Command translation failed for current command: {current_command}"))?
-                                }
-                            }
-                        } else {
-                            Err(format!(
-                                "Command arguments are invalid, please check: {:?} {:?}",
-                                segment, index
-                            ))?
-                        }
-                    }
-                    VMCommandType::Clabel => {
-                        let function_context;
-                        if let Some(previous_function) = function_call_stack.last() {
-                            function_context = previous_function.to_string();
-                        } else {
-                            // this scenario most likely happens when calling sys.init
-                            function_context = String::new();
-                        }
-
-                        if let Some(translated_command) =
-                            self.write_label(current_command, &function_context)
-                        {
-                            translated_vm_code.push_str(&translated_command);
-                            translated_vm_code.push('\n');
-                        } else {
-                            Err(format!(
-                                "Command translation failed for current command: {current_command}"
-                            ))?
-                        }
-                    }
-                    VMCommandType::Cgoto => {
-                        let function_context;
-                        if let Some(previous_function) = function_call_stack.last() {
-                            function_context = previous_function.to_string();
-                        } else {
-                            // this scenario most likely happens when calling sys.init
-                            function_context = String::new();
-                        }
-
-                        if let Some(translated_command) =
-                            self.write_goto(current_command, &function_context)
-                        {
-                            translated_vm_code.push_str(&translated_command);
-                            translated_vm_code.push('\n');
-                        } else {
-                            Err(format!(
-                                "Command translation failed for current command: {current_command}"
-                            ))?
-                        }
-                    }
-                    VMCommandType::Cif => {
-                        let function_context;
-                        if let Some(previous_function) = function_call_stack.last() {
-                            function_context = previous_function.to_string();
-                        } else {
-                            // this scenario most likely happens when calling sys.init
-                            function_context = String::new();
-                        }
-
-                        if let Some(translated_command) =
-                            self.write_if(current_command, &function_context)
-                        {
-                            translated_vm_code.push_str(&translated_command);
-                            translated_vm_code.push('\n');
-                        } else {
-                            Err(format!(
-                                "Command translation failed for current command: {current_command}"
-                            ))?
-                        }
-                    }
-                    VMCommandType::Ccall => {
-                        let function_name = self
-                            .code_parser
-                            .arg1(current_command, &VMCommandType::Ccall);
-                        let args = self
-                            .code_parser
-                            .arg2(current_command, &VMCommandType::Ccall);
-                        if let (Some(function_name), Some(args)) = (function_name, args) {
-                            let return_address;
-                            if let Some(previous_function) = function_call_stack.last() {
-                                dbg!(&function_call_stack);
-                                let call_count = function_call_stack
-                                    .iter()
-                                    .filter(|&s| s == previous_function)
-                                    .count();
-                                return_address = format!("{previous_function}$ret.{call_count}");
-                            } else {
-                                // this scenario most likely happens when calling sys.init
-                                return_address = format!("{file_name}.$ret");
-                            }
-
-                            function_call_stack.push(function_name.to_string());
-
-                            let args: i16 = args
-                                .parse()
-                                .expect("Parsing to i16 should have been validated");
-                            if let Some(translated_command) =
-                                self.write_call(function_name, args, &return_address)
-                            {
-                                translated_vm_code.push_str(&translated_command);
-                                translated_vm_code.push('\n');
-                            } else {
-                                Err(format!("Command translation failed for current command: {current_command}"))?
-                            }
-                        } else {
-                            Err(format!(
-                                "Command arguments are invalid, please check: {:?} {:?}",
-                                function_name, args
-                            ))?
-                        }
-                    }
-                    VMCommandType::Cfunction => {
-                        let function_name = self
-                            .code_parser
-                            .arg1(current_command, &VMCommandType::Cfunction);
-                        let local_vars = self
-                            .code_parser
-                            .arg2(current_command, &VMCommandType::Cfunction);
-                        if let (Some(function_name), Some(local_vars)) = (function_name, local_vars)
-                        {
-                            let local_vars: i16 = local_vars
-                                .parse()
-                                .expect("Parsing to i16 should have been validated");
-                            if let Some(translated_command) =
-                                self.write_function(function_name, local_vars)
-                            {
-                                translated_vm_code.push_str(&translated_command);
-                                translated_vm_code.push('\n');
-                            } else {
-                                Err(format!("Command translation failed for current command: {current_command}"))?
-                            }
-                        } else {
-                            Err(format!(
-                                "Command arguments are invalid, please check: {:?} {:?}",
-                                function_name, local_vars
-                            ))?
-                        }
-                    }
-                    VMCommandType::Creturn => {
-                        // pop function stack
-                        if let Some(translated_command) = self.write_return() {
-                            translated_vm_code.push_str(&translated_command);
-                            translated_vm_code.push('\n');
-                        } else {
-                            Err(format!(
-                                "Command translation failed for current command: {current_command}"
-                            ))?
-                        }
-                    }
-                }
+        let mut diagnostics = Diagnostics::new();
+        let mut writer_state = WriterState::default();
+        let mut vm_commands_iter = self.vm_commands.iter().peekable();
+        while let Some(parsed_command) = vm_commands_iter.next() {
+            let move_command = vm_commands_iter.peek().and_then(|next_command| {
+                self.write_forwarded_push(parsed_command, next_command, file_name)
+            });
+            let translated_command = if move_command.is_some() {
+                vm_commands_iter.next();
+                move_command
+            } else {
+                self.write_command(
+                    parsed_command,
+                    file_name,
+                    &mut writer_state,
+                    label_allocator,
+                )
+            };
 
-                line_number += 1;
+            if let Some(translated_command) = translated_command {
+                translated_vm_code.push_str(&translated_command);
+                translated_vm_code.push('\n');
             } else {
-                Err(format!(
-                    "Command is invalid, please check: {current_command}"
-                ))?
+                diagnostics.push(self.translation_error(parsed_command));
             }
         }
 
-        Ok(translated_vm_code)
+        if diagnostics.is_empty() {
+            Ok(translated_vm_code)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    // push x followed by pop y becomes a direct move from x to y at -O2
+    pub(crate) fn write_forwarded_push(
+        &self,
+        parsed_command: &ParsedCommand,
+        next_command: &ParsedCommand,
+        file_name: &str,
+    ) -> Option<String> {
+        if self.options.optimization_level < 2 {
+            return None;
+        }
+        match (&parsed_command.command, &next_command.command) {
+            (
+                VmCommand::Push { segment, index },
+                VmCommand::Pop {
+                    segment: pop_segment,
+                    index: pop_index,
+                },
+            ) => self.write_move((*segment, *index), (*pop_segment, *pop_index), file_name),
+            _ => None,
+        }
+    }
+
+    // None when the command can not be translated, see translation_error
+    pub(crate) fn write_command(
+        &self,
+        parsed_command: &ParsedCommand,
+        file_name: &str,
+        writer_state: &mut WriterState,
+        label_allocator: &mut LabelAllocator,
+    ) -> Option<String> {
+        let current_function = &writer_state.current_function;
+        match &parsed_command.command {
+            VmCommand::Arithmetic(op @ (ArithOp::Eq | ArithOp::Gt | ArithOp::Lt))
+                if self.options.compact =>
+            {
+                self.write_compact_comparison(*op, label_allocator)
+            }
+            VmCommand::Arithmetic(op) => self.write_arithmetic(*op, label_allocator),
+            VmCommand::Push { segment, index } => self.write_push(*segment, *index, file_name),
+            VmCommand::Pop { segment, index } => self.write_pop(*segment, *index, file_name),
+            VmCommand::Label(label_name) => self.write_label(label_name, current_function),
+            VmCommand::Goto(label_name) => self.write_goto(label_name, current_function),
+            VmCommand::IfGoto(label_name) => self.write_if(label_name, current_function),
+            VmCommand::Call { name, nargs } => {
+                // calls outside of any function, such as the bootstrap, use the file name
+                // and a number unique to the whole translation, the bootstrap is named
+                // after the program which can be the name of a file as well
+                let return_address = if current_function.is_empty() {
+                    format!("{file_name}$ret.{}", label_allocator.allocate_return())
+                } else {
+                    let return_address =
+                        format!("{current_function}$ret.{}", writer_state.call_count);
+                    writer_state.call_count += 1;
+                    return_address
+                };
+                if self.options.compact {
+                    self.write_compact_call(name, *nargs, &return_address)
+                } else {
+                    self.write_call(name, *nargs, &return_address)
+                }
+            }
+            VmCommand::Function { name, nlocals } => {
+                writer_state.current_function = name.to_string();
+                writer_state.call_count = 0;
+                self.write_function(name, *nlocals)
+            }
+            VmCommand::Return if self.options.compact => Some(String::from("@$$RETURN\n0;JMP")),
+            VmCommand::Return => self.write_return(),
+        }
+    }
+
+    // only push and pop can fail, for segments or indexes the parser would have rejected
+    pub(crate) fn translation_error(&self, parsed_command: &ParsedCommand) -> TranslateError {
+        let span = parsed_command.span.clone();
+        match &parsed_command.command {
+            VmCommand::Pop {
+                segment: Segment::Constant,
+                ..
+            } => TranslateError::BadSegment {
+                command: String::from("pop"),
+                segment: String::from("constant"),
+                span,
+            },
+            VmCommand::Push { segment, index } | VmCommand::Pop { segment, index } => {
+                TranslateError::IndexOutOfRange {
+                    segment: *segment,
+                    index: i32::from(*index),
+                    span,
+                }
+            }
+            current_command => TranslateError::UnknownCommand {
+                command: current_command.to_string(),
+                span,
+            },
+        }
     }
 
     pub fn write_init(&self) -> String {
+        let bootstrap_setup = &self.options.bootstrap_setup;
         let mut translated_command = String::from("");
-        // init stack pointer
-        translated_command.push_str("@256\nD=A\n@SP\nM=D\n");
+        // init stack pointer and any segment pointers that were asked for
+        let pointers = [
+            ("SP", Some(bootstrap_setup.sp)),
+            ("LCL", bootstrap_setup.lcl),
+            ("ARG", bootstrap_setup.arg),
+            ("THIS", bootstrap_setup.this),
+            ("THAT", bootstrap_setup.that),
+        ];
+        for (pointer, value) in pointers {
+            if let Some(value) = value {
+                translated_command.push_str(&format!("@{value}\nD=A\n@{pointer}\nM=D\n"));
+            }
+        }
 
         translated_command
     }
 
-    fn write_label(&self, label_command: &str, function_context: &str) -> Option<String> {
-        let mut translated_command = String::from("");
-        if let Some(label_name) = self.code_parser.arg1(label_command, &VMCommandType::Clabel) {
-            if function_context.is_empty() {
-                translated_command.push_str(&format!("({label_name})"));
-            } else {
-                translated_command.push_str(&format!("({function_context}${label_name})"));
-            }
+    // the shared routines of compact mode, jumped over when the program starts
+    pub fn write_compact_routines(&self) -> String {
+        let mut translated_command = String::from("@$$START\n0;JMP\n");
+        // $$CMP, entered at $$CMP.EQ, $$CMP.GT or $$CMP.LT with the return address in D
+        // pops y and replaces x with the result of x eq/gt/lt y
+        let compare = "@R13\nM=D\n@SP\nAM=M-1\nD=M\nA=A-1\nD=M-D\n@$$CMP.TRUE\n";
+        translated_command.push_str(&format!(
+            "($$CMP.EQ)\n{compare}D;JEQ\n@$$CMP.FALSE\n0;JMP\n"
+        ));
+        translated_command.push_str(&format!(
+            "($$CMP.GT)\n{compare}D;JGT\n@$$CMP.FALSE\n0;JMP\n"
+        ));
+        translated_command.push_str(&format!("($$CMP.LT)\n{compare}D;JLT\n"));
+        translated_command.push_str("($$CMP.FALSE)\nD=0\n@$$CMP.PUSH\n0;JMP\n($$CMP.TRUE)\nD=-1\n");
+        translated_command.push_str("($$CMP.PUSH)\n@SP\nA=M-1\nM=D\n@R13\nA=M\n0;JMP\n");
+        // $$CALL, entered with the return address in D, the function address in R13 and nargs in R14
+        translated_command.push_str("($$CALL)\n@SP\nA=M\nM=D\n@SP\nM=M+1\n");
+        let assign_sp = "D=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n";
+        translated_command.push_str(&format!("@LCL\n{assign_sp}"));
+        translated_command.push_str(&format!("@ARG\n{assign_sp}"));
+        translated_command.push_str(&format!("@THIS\n{assign_sp}"));
+        translated_command.push_str(&format!("@THAT\n{assign_sp}"));
+        translated_command.push_str("@R14\nD=M\n@5\nD=D+A\n@SP\nD=M-D\n@ARG\nM=D\n");
+        translated_command.push_str("@SP\nD=M\n@LCL\nM=D\n@R13\nA=M\n0;JMP\n");
+        // $$RETURN, the same as an inlined return
+        translated_command.push_str("($$RETURN)\n");
+        translated_command.push_str(&self.write_return().unwrap_or_default());
+        translated_command.push_str("\n($$START)\n");
+
+        translated_command
+    }
+
+    fn write_compact_comparison(
+        &self,
+        op: ArithOp,
+        label_allocator: &mut LabelAllocator,
+    ) -> Option<String> {
+        let entry = match op {
+            ArithOp::Eq => "$$CMP.EQ",
+            ArithOp::Gt => "$$CMP.GT",
+            ArithOp::Lt => "$$CMP.LT",
+            _ => return None,
+        };
+        let return_address = format!("$$CMP$ret.{}", label_allocator.allocate());
+
+        Some(format!(
+            "@{return_address}\nD=A\n@{entry}\n0;JMP\n({return_address})"
+        ))
+    }
+
+    fn write_compact_call(
+        &self,
+        function_name: &str,
+        args: u16,
+        return_address: &str,
+    ) -> Option<String> {
+        Some(format!(
+            "@{function_name}\nD=A\n@R13\nM=D\n@{args}\nD=A\n@R14\nM=D\n@{return_address}\nD=A\n@$$CALL\n0;JMP\n({return_address})"
+        ))
+    }
 
-            Some(translated_command)
+    fn write_label(&self, label_name: &str, function_context: &str) -> Option<String> {
+        let mut translated_command = String::from("");
+        if function_context.is_empty() {
+            translated_command.push_str(&format!("({label_name})"));
         } else {
-            None
+            translated_command.push_str(&format!("({function_context}${label_name})"));
         }
+
+        Some(translated_command)
     }
 
-    fn write_goto(&self, goto_command: &str, function_context: &str) -> Option<String> {
+    fn write_goto(&self, label_name: &str, function_context: &str) -> Option<String> {
         let mut translated_command = String::from("");
-        if let Some(label_name) = self.code_parser.arg1(goto_command, &VMCommandType::Cgoto) {
-            if function_context.is_empty() {
-                translated_command.push_str(&format!("@{label_name}\n0;JMP"));
-            } else {
-                translated_command.push_str(&format!("@{function_context}${label_name}\n0;JMP"));
-            }
-
-            Some(translated_command)
+        if function_context.is_empty() {
+            translated_command.push_str(&format!("@{label_name}\n0;JMP"));
         } else {
-            None
+            translated_command.push_str(&format!("@{function_context}${label_name}\n0;JMP"));
         }
+
+        Some(translated_command)
     }
 
-    fn write_if(&self, if_command: &str, function_context: &str) -> Option<String> {
+    fn write_if(&self, label_name: &str, function_context: &str) -> Option<String> {
         let mut translated_command = String::from("");
-        if let Some(label_name) = self.code_parser.arg1(if_command, &VMCommandType::Cif) {
-            if function_context.is_empty() {
-                translated_command.push_str(&format!("@SP\nAM=M-1\nD=M\n@{label_name}\nD;JNE"));
-            } else {
-                translated_command.push_str(&format!(
-                    "@SP\nAM=M-1\nD=M\n@{function_context}${label_name}\nD;JNE"
-                ));
-            }
-
-            Some(translated_command)
+        if function_context.is_empty() {
+            translated_command.push_str(&format!("@SP\nAM=M-1\nD=M\n@{label_name}\nD;JNE"));
         } else {
-            None
+            translated_command.push_str(&format!(
+                "@SP\nAM=M-1\nD=M\n@{function_context}${label_name}\nD;JNE"
+            ));
         }
+
+        Some(translated_command)
     }
 
-    fn write_function(&self, function_name: &str, local_vars: i16) -> Option<String> {
+    fn write_function(&self, function_name: &str, local_vars: u16) -> Option<String> {
         let mut translated_command = String::from("");
         translated_command.push_str(&format!("({function_name})\n"));
         // intialize local memory segment on global stack for current called function
@@ -438,7 +974,7 @@ impl VmCodeWriter {
         Some(translated_command)
     }
 
-    fn write_call(&self, function_name: &str, args: i16, return_address: &str) -> Option<String> {
+    fn write_call(&self, function_name: &str, args: u16, return_address: &str) -> Option<String> {
         let mut translated_command = String::from("");
         // save return_address
         translated_command.push_str(&format!(
@@ -486,170 +1022,280 @@ impl VmCodeWriter {
         Some(translated_command)
     }
 
-    fn write_push(
-        &self,
-        segment_value: &str,
-        index_value: &str,
-        segment_list: &[&str],
-        file_name: &str,
-    ) -> Option<String> {
-        let mut translated_command = String::from("");
+    fn write_push(&self, segment: Segment, index_value: u16, file_name: &str) -> Option<String> {
+        let load = self.write_load(segment, index_value, file_name)?;
         let increment_sp = "@SP\nA=M\nM=D\n@SP\nM=M+1";
-        if segment_list.contains(&segment_value) {
-            let segment_value_upper_case: &str = &segment_value.to_uppercase();
-            match segment_value_upper_case {
-                "CONSTANT" => {
-                    translated_command.push_str(&format!("@{index_value}\nD=A\n"));
-                }
-                "STATIC" => {
-                    translated_command.push_str(&format!("@{file_name}.{index_value}\nD=M\n"));
-                }
-                "POINTER" => {
-                    let pointer_end = "D=M\n";
-                    if index_value == "0" {
-                        translated_command.push_str("@THIS\n");
-                        translated_command.push_str(pointer_end);
-                    } else if index_value == "1" {
-                        translated_command.push_str("@THAT\n");
-                        translated_command.push_str(pointer_end);
-                    }
-                }
-                "TEMP" => {
-                    translated_command.push_str(&format!("@{index_value}\nD=A\n@5\nA=D+A\nD=M\n"));
-                }
-                "LOCAL" => {
-                    translated_command
-                        .push_str(&format!("@LCL\nD=M\n@{index_value}\nA=D+A\nD=M\n"));
-                }
-                "ARGUMENT" => {
-                    translated_command
-                        .push_str(&format!("@ARG\nD=M\n@{index_value}\nA=D+A\nD=M\n"));
-                }
-                _ => {
-                    translated_command.push_str(&format!(
-                        "@{segment_value_upper_case}\nD=M\n@{index_value}\nA=D+A\nD=M\n"
-                    ));
-                }
-            }
-        }
+        Some(format!("{load}{increment_sp}"))
+    }
 
-        if !translated_command.is_empty() {
-            translated_command.push_str(increment_sp);
-            Some(translated_command)
-        } else {
-            None
+    // the value of a push in D, without touching the stack
+    fn write_load(&self, segment: Segment, index_value: u16, file_name: &str) -> Option<String> {
+        if index_value > segment.max_index() {
+            return None;
         }
+        let load = match segment {
+            Segment::Constant => format!("@{index_value}\nD=A\n"),
+            Segment::Static => format!("@{file_name}.{index_value}\nD=M\n"),
+            Segment::Pointer if index_value == 0 => String::from("@THIS\nD=M\n"),
+            Segment::Pointer => String::from("@THAT\nD=M\n"),
+            Segment::Temp => format!("@{index_value}\nD=A\n@5\nA=D+A\nD=M\n"),
+            Segment::Local => format!("@LCL\nD=M\n@{index_value}\nA=D+A\nD=M\n"),
+            Segment::Argument => format!("@ARG\nD=M\n@{index_value}\nA=D+A\nD=M\n"),
+            Segment::This | Segment::That => {
+                let segment_value_upper_case = segment.name().to_uppercase();
+                format!("@{segment_value_upper_case}\nD=M\n@{index_value}\nA=D+A\nD=M\n")
+            }
+        };
+
+        Some(load)
     }
 
-    fn write_pop(
+    // the address computation of the pop, then the load of the push, then the store of the pop
+    fn write_move(
         &self,
-        segment_value: &str,
-        index_value: &str,
-        segment_list: &[&str],
+        (push_segment, push_index): (Segment, u16),
+        (pop_segment, pop_index): (Segment, u16),
         file_name: &str,
     ) -> Option<String> {
-        let mut translated_command = String::from("");
+        let load = self.write_load(push_segment, push_index, file_name)?;
+        let (address, store) = self.write_store(pop_segment, pop_index, file_name)?;
 
-        if segment_list.contains(&segment_value) {
-            let deref_sp = "@SP\nAM=M-1\nD=M\n";
-            let segment_value_upper_case: &str = &segment_value.to_uppercase();
-            match segment_value_upper_case {
-                "STATIC" => {
-                    translated_command
-                        .push_str(&format!("{deref_sp}@{file_name}.{index_value}\nM=D"));
-                }
-                "POINTER" => {
-                    let pointer_end = "M=D";
-                    if index_value == "0" {
-                        translated_command.push_str(&format!("{deref_sp}@THIS\n"));
-                        translated_command.push_str(pointer_end);
-                    } else if index_value == "1" {
-                        translated_command.push_str(&format!("{deref_sp}@THAT\n"));
-                        translated_command.push_str(pointer_end);
-                    }
-                }
-                "TEMP" => {
-                    translated_command.push_str(&format!(
-                        "@5\nD=A\n@{index_value}\nD=D+A\n@R13\nM=D\n{deref_sp}@R13\nA=M\nM=D"
-                    ));
-                }
-                "LOCAL" => {
-                    translated_command.push_str(&format!(
-                        "@{index_value}\nD=A\n@LCL\nD=D+M\n@R13\nM=D\n{deref_sp}@R13\nA=M\nM=D"
-                    ));
-                }
-                "ARGUMENT" => {
-                    translated_command.push_str(&format!(
-                        "@{index_value}\nD=A\n@ARG\nD=D+M\n@R13\nM=D\n{deref_sp}@R13\nA=M\nM=D"
-                    ));
-                }
-                _ => {
-                    translated_command.push_str(&format!("@{index_value}\nD=A\n@{segment_value_upper_case}\nD=D+M\n@R13\nM=D\n{deref_sp}@R13\nA=M\nM=D"));
-                }
-            }
-        }
+        Some(format!("{address}{load}{store}"))
+    }
 
-        if !translated_command.is_empty() {
-            Some(translated_command)
-        } else {
-            None
+    fn write_pop(&self, segment: Segment, index_value: u16, file_name: &str) -> Option<String> {
+        let (address, store) = self.write_store(segment, index_value, file_name)?;
+        let deref_sp = "@SP\nAM=M-1\nD=M\n";
+        Some(format!("{address}{deref_sp}{store}"))
+    }
+
+    // a pop split around taking the value off the stack: the code that works out the
+    // target address into R13, empty for fixed addresses, and the code storing D there
+    fn write_store(
+        &self,
+        segment: Segment,
+        index_value: u16,
+        file_name: &str,
+    ) -> Option<(String, String)> {
+        if index_value > segment.max_index() {
+            return None;
         }
+        let store_r13 = String::from("@R13\nA=M\nM=D");
+        let parts = match segment {
+            Segment::Constant => return None,
+            Segment::Static => (String::new(), format!("@{file_name}.{index_value}\nM=D")),
+            Segment::Pointer if index_value == 0 => (String::new(), String::from("@THIS\nM=D")),
+            Segment::Pointer => (String::new(), String::from("@THAT\nM=D")),
+            Segment::Temp => (
+                format!("@5\nD=A\n@{index_value}\nD=D+A\n@R13\nM=D\n"),
+                store_r13,
+            ),
+            Segment::Local => (
+                format!("@{index_value}\nD=A\n@LCL\nD=D+M\n@R13\nM=D\n"),
+                store_r13,
+            ),
+            Segment::Argument => (
+                format!("@{index_value}\nD=A\n@ARG\nD=D+M\n@R13\nM=D\n"),
+                store_r13,
+            ),
+            Segment::This | Segment::That => {
+                let segment_value_upper_case = segment.name().to_uppercase();
+                (
+                    format!("@{index_value}\nD=A\n@{segment_value_upper_case}\nD=D+M\n@R13\nM=D\n"),
+                    store_r13,
+                )
+            }
+        };
+
+        Some(parts)
     }
 
     fn write_arithmetic(
         &self,
-        current_command: &str,
-        segment_list: &[&str],
-        line_number: &i16,
+        op: ArithOp,
+        label_allocator: &mut LabelAllocator,
     ) -> Option<String> {
         let mut translated_command = String::from("");
-
-        if segment_list.contains(&current_command) {
-            let deref_sp = "@SP\nAM=M-1\nD=M\n";
-            let push_bool = "@SP\nA=M-1\nM=D";
-            match current_command {
-                "add" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nM=D+M"));
-                }
-                "sub" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nM=M-D"));
-                }
-                "neg" => {
-                    translated_command.push_str("@SP\nA=M-1\nM=-M");
-                }
-                "eq" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nD=M-D\n@equal.{line_number}\nD;JEQ\nD=0\n@done.{line_number}\n0;JMP\n(equal.{line_number})\nD=-1\n(done.{line_number})\n{push_bool}"));
-                }
-                "gt" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nD=M-D\n@greater.{line_number}\nD;JGT\nD=0\n@done.{line_number}\n0;JMP\n(greater.{line_number})\nD=-1\n(done.{line_number})\n{push_bool}"));
-                }
-                "lt" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nD=M-D\n@lesser.{line_number}\nD;JLT\nD=0\n@done.{line_number}\n0;JMP\n(lesser.{line_number})\nD=-1\n(done.{line_number})\n{push_bool}"));
-                }
-                "and" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nM=D&M"));
-                }
-                "or" => {
-                    translated_command.push_str(&format!("{deref_sp}A=A-1\nM=D|M"));
-                }
-                "not" => {
-                    translated_command.push_str("@SP\nA=M-1\nM=!M");
-                }
-                _ => {}
+        let deref_sp = "@SP\nAM=M-1\nD=M\n";
+        let push_bool = "@SP\nA=M-1\nM=D";
+        match op {
+            ArithOp::Add => {
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nM=D+M"));
+            }
+            ArithOp::Sub => {
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nM=M-D"));
+            }
+            ArithOp::Neg => {
+                translated_command.push_str("@SP\nA=M-1\nM=-M");
+            }
+            ArithOp::Eq => {
+                let label = label_allocator.allocate();
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nD=M-D\n@equal.{label}\nD;JEQ\nD=0\n@done.{label}\n0;JMP\n(equal.{label})\nD=-1\n(done.{label})\n{push_bool}"));
+            }
+            ArithOp::Gt => {
+                let label = label_allocator.allocate();
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nD=M-D\n@greater.{label}\nD;JGT\nD=0\n@done.{label}\n0;JMP\n(greater.{label})\nD=-1\n(done.{label})\n{push_bool}"));
+            }
+            ArithOp::Lt => {
+                let label = label_allocator.allocate();
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nD=M-D\n@lesser.{label}\nD;JLT\nD=0\n@done.{label}\n0;JMP\n(lesser.{label})\nD=-1\n(done.{label})\n{push_bool}"));
+            }
+            ArithOp::And => {
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nM=D&M"));
+            }
+            ArithOp::Or => {
+                translated_command.push_str(&format!("{deref_sp}A=A-1\nM=D|M"));
+            }
+            ArithOp::Not => {
+                translated_command.push_str("@SP\nA=M-1\nM=!M");
             }
         }
 
-        if !translated_command.is_empty() {
-            Some(translated_command)
-        } else {
-            None
+        Some(translated_command)
+    }
+}
+
+// the endless loop a program without bootstrap code stops in
+const END_LOOP: &str = "(end_asm_file)\n@end_asm_file\n0;JMP";
+
+// sets up the pointers and calls the entry function, see BootstrapSetup
+fn write_bootstrap(
+    program_name: &str,
+    options: &TranslateOptions,
+    label_allocator: &mut LabelAllocator,
+) -> Result<String, Diagnostics> {
+    let bootstrap_setup = &options.bootstrap_setup;
+    let init_code_writer = VmCodeWriter::with_options(
+        vec![ParsedCommand::new(
+            VmCommand::Call {
+                name: bootstrap_setup.entry_function.clone(),
+                nargs: bootstrap_setup.entry_nargs,
+            },
+            Span::default(),
+        )],
+        options.clone(),
+    );
+    let mut bootstrap_code = init_code_writer.write_init();
+    bootstrap_code.push_str(&init_code_writer.translate(program_name, label_allocator)?);
+    // Sys.init never returns, any other entry function stops here when it does
+    if bootstrap_setup.entry_function != "Sys.init" {
+        bootstrap_code.push_str(END_LOOP);
+        bootstrap_code.push('\n');
+    }
+
+    Ok(bootstrap_code)
+}
+
+// translates all files of a program into one assembly program
+// bootstrap code calling Sys.init is emitted first when there is a Sys file,
+// otherwise the program ends in an endless loop
+// translate_program_with_options can force or suppress it and change what it sets up
+pub fn translate_program(vm_files: Vec<VmFile>, program_name: &str) -> Result<String, Diagnostics> {
+    translate_program_with_options(vm_files, program_name, &TranslateOptions::default())
+}
+
+// same as translate_program with code generation settings such as compact mode
+pub fn translate_program_with_options(
+    vm_files: Vec<VmFile>,
+    program_name: &str,
+    options: &TranslateOptions,
+) -> Result<String, Diagnostics> {
+    validate_vm_files(&vm_files)?;
+    let mut translated_program = String::new();
+    let mut diagnostics = Diagnostics::new();
+
+    let bootstrap_code_exists = options.emits_bootstrap(&vm_files);
+    let vm_files = match options.reachability_roots(&vm_files)? {
+        Some(roots) => remove_unreachable_functions(vm_files, &roots).0,
+        None => vm_files,
+    };
+    let vm_files = if options.optimization_level >= 2 {
+        optimize_vm_files(vm_files)
+    } else {
+        vm_files
+    };
+    let mut label_allocator = LabelAllocator::new();
+
+    if options.compact {
+        translated_program.push_str(&VmCodeWriter::new(Vec::new()).write_compact_routines());
+    }
+
+    if bootstrap_code_exists {
+        match write_bootstrap(program_name, options, &mut label_allocator) {
+            Ok(bootstrap_code) => translated_program.push_str(&bootstrap_code),
+            Err(bootstrap_diagnostics) => diagnostics.extend(bootstrap_diagnostics),
+        }
+    }
+
+    for vm_file in vm_files {
+        let vm_code_writer = VmCodeWriter::with_options(vm_file.commands, options.clone());
+        match vm_code_writer.translate(&vm_file.name, &mut label_allocator) {
+            Ok(translated_vm_code) => translated_program.push_str(&translated_vm_code),
+            Err(file_diagnostics) => diagnostics.extend(file_diagnostics),
         }
     }
+
+    if !bootstrap_code_exists {
+        // set end of file
+        translated_program.push_str(END_LOOP);
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    if options.optimization_level >= 1 {
+        translated_program = optimize_asm(&translated_program);
+    }
+
+    Ok(translated_program)
+}
+
+// parses (file name, vm code) pairs into the vm files of one program, for tests
+#[cfg(test)]
+pub(crate) fn parse_vm_fixture(vm_files: &[(impl AsRef<str>, impl AsRef<str>)]) -> Vec<VmFile> {
+    let command_symbol_table = get_command_symbol_table();
+    let vm_code_parser = VmCodeParser::new();
+    vm_files
+        .iter()
+        .map(|(name, vm_code)| {
+            let name = name.as_ref();
+            let vm_commands = vm_code_parser
+                .parse(vm_code.as_ref(), name, &command_symbol_table)
+                .expect("Should be valid");
+            VmFile::new(name, vm_commands)
+        })
+        .collect()
+}
+
+// the (file name, vm code) pairs of a program directory below 08, Sys first like the cli
+#[cfg(test)]
+pub(crate) fn read_vm_fixture(fixture: &str) -> Vec<(String, String)> {
+    let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("08")
+        .join(fixture);
+    let mut vm_paths: Vec<_> = std::fs::read_dir(directory)
+        .expect("Should be valid")
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+        .collect();
+    vm_paths.sort_by_key(|path| (!path.ends_with("Sys.vm"), path.clone()));
+
+    vm_paths
+        .iter()
+        .map(|path| {
+            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap();
+            let vm_code = std::fs::read_to_string(path).expect("Should be valid");
+            (name.to_string(), vm_code)
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test] // test for removing comments and blank lines
     fn parse_clean_instructions() {
@@ -758,4 +1404,531 @@ mod tests {
         assert!(return_arg1.is_none());
         assert!(return_arg2.is_none());
     }
+
+    #[test]
+    fn parse_vm_commands() {
+        let command_symbol_table = get_command_symbol_table();
+        let test_parser = VmCodeParser::new();
+        let input = "// header\nfunction Main.fibonacci 0\n\tpush argument 0\nlt // compare\nif-goto N_LT_2\ngoto END\nlabel N_LT_2\npop pointer 1\ncall Main.fibonacci 1\nreturn";
+        let vm_commands: Vec<VmCommand> = test_parser
+            .parse(input, "Main.vm", &command_symbol_table)
+            .expect("Should be valid")
+            .into_iter()
+            .map(|parsed_command| parsed_command.command)
+            .collect();
+
+        assert_eq!(
+            vec![
+                VmCommand::Function {
+                    name: "Main.fibonacci".to_string(),
+                    nlocals: 0
+                },
+                VmCommand::Push {
+                    segment: Segment::Argument,
+                    index: 0
+                },
+                VmCommand::Arithmetic(ArithOp::Lt),
+                VmCommand::IfGoto("N_LT_2".to_string()),
+                VmCommand::Goto("END".to_string()),
+                VmCommand::Label("N_LT_2".to_string()),
+                VmCommand::Pop {
+                    segment: Segment::Pointer,
+                    index: 1
+                },
+                VmCommand::Call {
+                    name: "Main.fibonacci".to_string(),
+                    nargs: 1
+                },
+                VmCommand::Return,
+            ],
+            vm_commands
+        );
+
+        // displaying the commands gives back the cleaned vm code
+        let re_emitted: Vec<String> = vm_commands.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            test_parser.clean_vm_code(input.to_string()),
+            re_emitted.join("\n")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_vm_commands() {
+        let command_symbol_table = get_command_symbol_table();
+        let test_parser = VmCodeParser::new();
+        let first_error = |vm_code: &str| {
+            test_parser
+                .parse(vm_code, "Foo.vm", &command_symbol_table)
+                .expect_err("Should be invalid")
+                .into_iter()
+                .next()
+                .expect("Should have an error")
+        };
+
+        assert!(matches!(
+            first_error("yo mama"),
+            TranslateError::UnknownCommand { command, .. } if command == "yo"
+        ));
+        assert!(matches!(
+            first_error("pop constant 3"),
+            TranslateError::BadSegment { segment, .. } if segment == "constant"
+        ));
+        assert!(matches!(
+            first_error("push local -1"),
+            TranslateError::IndexOutOfRange { index: -1, .. }
+        ));
+        assert!(matches!(
+            first_error("push local one"),
+            TranslateError::MalformedInteger { value, .. } if value == "one"
+        ));
+        assert!(matches!(
+            first_error("call Foo.bar"),
+            TranslateError::MissingArgument { command, .. } if command == "call"
+        ));
+    }
+
+    #[test]
+    fn parse_any_whitespace() {
+        let command_symbol_table = get_command_symbol_table();
+        let test_parser = VmCodeParser::new();
+        // tabs, crlf line endings, repeated spaces and non breaking or ideographic spaces
+        let input = "function\tMain.main  0\r\n\tpush \t constant\t7\r\npop\u{a0}temp\u{3000}1 \t// seven\r\n  add\t\r\ncall   Main.main\t\t1\r\n\treturn\r\n";
+        let vm_commands: Vec<String> = test_parser
+            .parse(input, "Main.vm", &command_symbol_table)
+            .expect("Should be valid")
+            .into_iter()
+            .map(|parsed_command| parsed_command.command.to_string())
+            .collect();
+
+        assert_eq!(
+            vec![
+                "function Main.main 0",
+                "push constant 7",
+                "pop temp 1",
+                "add",
+                "call Main.main 1",
+                "return"
+            ],
+            vm_commands
+        );
+    }
+
+    #[test]
+    fn parse_whole_words_only() {
+        let command_symbol_table = get_command_symbol_table();
+        let test_parser = VmCodeParser::new();
+        for vm_code in [
+            "pushy constant 1",
+            "pushconstant 1",
+            "addition",
+            "returns",
+            "gotoEND",
+        ] {
+            assert!(test_parser
+                .command_type(vm_code, &command_symbol_table)
+                .is_none());
+            assert!(matches!(
+                test_parser
+                    .parse(vm_code, "Foo.vm", &command_symbol_table)
+                    .expect_err("Should be invalid")
+                    .into_iter()
+                    .next(),
+                Some(TranslateError::UnknownCommand { .. })
+            ));
+        }
+
+        let diagnostics = test_parser
+            .parse(
+                "push constant 1 2\nreturn 4\nlabel\tLOOP  END\nadd add",
+                "Foo.vm",
+                &command_symbol_table,
+            )
+            .expect_err("Should be invalid");
+        let trailing_tokens: Vec<(String, usize, usize)> = diagnostics
+            .iter()
+            .map(|error| match error {
+                TranslateError::TrailingToken { token, span, .. } => {
+                    (token.clone(), span.line, span.column)
+                }
+                error => panic!("{error}"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("2".to_string(), 1, 17),
+                ("4".to_string(), 2, 8),
+                ("END".to_string(), 3, 13),
+                ("add".to_string(), 4, 5)
+            ],
+            trailing_tokens
+        );
+    }
+
+    #[test]
+    fn translate_invalid_pointer_index() {
+        let vm_code_writer = VmCodeWriter::new(vec![ParsedCommand::new(
+            VmCommand::Push {
+                segment: Segment::Pointer,
+                index: 2,
+            },
+            Span::default(),
+        )]);
+        let diagnostics = vm_code_writer
+            .translate("Foo", &mut LabelAllocator::new())
+            .expect_err("Should be invalid");
+
+        assert!(matches!(
+            diagnostics.iter().next(),
+            Some(TranslateError::IndexOutOfRange {
+                segment: Segment::Pointer,
+                index: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn translate_labels_scoped_by_function_declaration() {
+        let vm_files = parse_vm_fixture(&[
+            (
+                "Sys",
+                "function Sys.init 0\ncall Main.fibonacci 0\ncall Foo.bar 0\nlabel END\ngoto END",
+            ),
+            (
+                "Main",
+                "function Main.fibonacci 0\nlabel LOOP\nif-goto LOOP\ngoto LOOP\npush constant 0\nreturn",
+            ),
+        ]);
+        let translated_program = translate_program(vm_files, "Program").expect("Should be valid");
+
+        assert!(translated_program.contains("(Sys.init$END)\n@Sys.init$END\n0;JMP"));
+        assert!(translated_program.contains("(Main.fibonacci$LOOP)"));
+        assert!(translated_program.contains("@Main.fibonacci$LOOP\nD;JNE"));
+        assert!(translated_program.contains("@Main.fibonacci$LOOP\n0;JMP"));
+        assert!(!translated_program.contains("Foo.bar$"));
+    }
+
+    #[test]
+    fn translate_unique_return_addresses() {
+        let mut vm_code = String::new();
+        for function_index in 0..3 {
+            vm_code.push_str(&format!("function Main.caller{function_index} 0\n"));
+            for call_index in 0..20 {
+                vm_code.push_str(&format!("call Main.callee{} 0\n", call_index % 4));
+            }
+            vm_code.push_str("return\n");
+        }
+        let vm_commands = VmCodeParser::new()
+            .parse(&vm_code, "Main", &get_command_symbol_table())
+            .expect("Should be valid");
+        let translated_vm_code = VmCodeWriter::new(vm_commands)
+            .translate("Main", &mut LabelAllocator::new())
+            .expect("Should be valid");
+
+        let return_labels: Vec<&str> = translated_vm_code
+            .lines()
+            .filter(|line| line.starts_with('(') && line.contains("$ret."))
+            .collect();
+        let unique_return_labels: HashSet<&str> = return_labels.iter().copied().collect();
+        assert_eq!(60, return_labels.len());
+        assert_eq!(60, unique_return_labels.len());
+        assert_eq!("(Main.caller0$ret.0)", return_labels[0]);
+        assert_eq!("(Main.caller2$ret.19)", return_labels[59]);
+
+        // the bootstrap and calls outside of any function of a file named like the program
+        let vm_files = parse_vm_fixture(&[(
+            "Foo",
+            "call Foo.f 0\nfunction Foo.f 0\ncall Foo.f 0\nreturn",
+        )]);
+        let options = TranslateOptions {
+            bootstrap: Bootstrap::Force,
+            bootstrap_setup: BootstrapSetup {
+                entry_function: String::from("Foo.f"),
+                ..BootstrapSetup::default()
+            },
+            ..TranslateOptions::default()
+        };
+        let translated_vm_code =
+            translate_program_with_options(vm_files, "Foo", &options).expect("Should be valid");
+        let return_labels: Vec<&str> = translated_vm_code
+            .lines()
+            .filter(|line| line.starts_with('(') && line.contains("$ret."))
+            .collect();
+        assert_eq!(
+            vec!["(Foo$ret.0)", "(Foo$ret.1)", "(Foo.f$ret.0)"],
+            return_labels
+        );
+    }
+
+    #[test]
+    fn translate_unique_comparison_labels_across_files() {
+        let comparisons = "push constant 1\npush constant 2\neq\npush constant 3\ngt\npush constant 4\nlt\nreturn";
+        let vm_files = parse_vm_fixture(&[
+            ("Sys", include_str!("../08/StaticsTest/Sys.vm").to_string()),
+            (
+                "Class1",
+                include_str!("../08/StaticsTest/Class1.vm").to_string(),
+            ),
+            (
+                "Class2",
+                include_str!("../08/StaticsTest/Class2.vm").to_string(),
+            ),
+            (
+                "Compare1",
+                format!("function Compare1.run 0\n{comparisons}"),
+            ),
+            (
+                "Compare2",
+                format!("function Compare2.run 0\n{comparisons}"),
+            ),
+            (
+                "Compare3",
+                format!("function Compare3.run 0\n{comparisons}"),
+            ),
+        ]);
+        let translated_program =
+            translate_program(vm_files, "StaticsTest").expect("Should be valid");
+
+        let labels: Vec<&str> = translated_program
+            .lines()
+            .filter(|line| line.starts_with('('))
+            .collect();
+        let unique_labels: HashSet<&str> = labels.iter().copied().collect();
+        assert_eq!(labels.len(), unique_labels.len());
+        assert_eq!(
+            9,
+            labels
+                .iter()
+                .filter(|label| label.starts_with("(done."))
+                .count()
+        );
+    }
+
+    #[test]
+    fn translate_compact_shares_routines() {
+        let parse_fixture = || {
+            parse_vm_fixture(&[
+                ("Sys", include_str!("../08/FibonacciElement/Sys.vm")),
+                ("Main", include_str!("../08/FibonacciElement/Main.vm")),
+                ("StackTest", include_str!("../StackTest.vm")),
+            ])
+        };
+        let instruction_count = |asm_code: &str| {
+            asm_code
+                .lines()
+                .filter(|line| !line.starts_with('('))
+                .count()
+        };
+
+        let inlined = translate_program(parse_fixture(), "Program").expect("Should be valid");
+        let compact = translate_program_with_options(
+            parse_fixture(),
+            "Program",
+            &TranslateOptions {
+                compact: true,
+                ..TranslateOptions::default()
+            },
+        )
+        .expect("Should be valid");
+
+        assert!(instruction_count(&compact) < instruction_count(&inlined));
+        for routine in ["($$CMP.EQ)", "($$CALL)", "($$RETURN)"] {
+            assert_eq!(1, compact.matches(routine).count());
+        }
+        assert!(!compact.contains("(done."));
+    }
+
+    #[test]
+    fn translate_forwards_push_into_pop() {
+        let vm_commands = VmCodeParser::new()
+            .parse(
+                "push local 2\npop static 3\npush constant 7\npop that 1\npush temp 0\nadd",
+                "Foo",
+                &get_command_symbol_table(),
+            )
+            .expect("Should be valid");
+        let vm_code_writer = VmCodeWriter::with_options(
+            vm_commands,
+            TranslateOptions {
+                optimization_level: 2,
+                ..TranslateOptions::default()
+            },
+        );
+        let translated_vm_code = vm_code_writer
+            .translate("Foo", &mut LabelAllocator::new())
+            .expect("Should be valid");
+
+        assert_eq!(
+            "@LCL\nD=M\n@2\nA=D+A\nD=M\n@Foo.3\nM=D\n\
+             @1\nD=A\n@THAT\nD=D+M\n@R13\nM=D\n@7\nD=A\n@R13\nA=M\nM=D\n\
+             @0\nD=A\n@5\nA=D+A\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n\
+             @SP\nAM=M-1\nD=M\nA=A-1\nM=D+M\n",
+            translated_vm_code
+        );
+    }
+
+    #[test]
+    fn translate_bootstrap_modes() {
+        let translate_with_bootstrap = |name: &str, bootstrap: Bootstrap| {
+            translate_program_with_options(
+                parse_vm_fixture(&[(name, "function Sys.init 0\nlabel END\ngoto END")]),
+                "Program",
+                &TranslateOptions {
+                    bootstrap,
+                    ..TranslateOptions::default()
+                },
+            )
+            .expect("Should be valid")
+        };
+        let has_bootstrap =
+            |asm_code: &str| asm_code.starts_with("@256\nD=A\n@SP\nM=D\n@Program$ret.0");
+        let has_end_loop =
+            |asm_code: &str| asm_code.ends_with("(end_asm_file)\n@end_asm_file\n0;JMP");
+
+        let auto = translate_with_bootstrap("Sys", Bootstrap::Auto);
+        assert!(has_bootstrap(&auto) && !has_end_loop(&auto));
+        let auto = translate_with_bootstrap("Main", Bootstrap::Auto);
+        assert!(!has_bootstrap(&auto) && has_end_loop(&auto));
+        let forced = translate_with_bootstrap("Main", Bootstrap::Force);
+        assert!(has_bootstrap(&forced) && !has_end_loop(&forced));
+        let suppressed = translate_with_bootstrap("Sys", Bootstrap::Suppress);
+        assert!(!has_bootstrap(&suppressed) && has_end_loop(&suppressed));
+    }
+
+    #[test]
+    fn translate_simple_function_with_bootstrap_setup() {
+        // the frame SimpleFunction.tst builds by hand is pushed by the bootstrap call instead
+        let vm_files = parse_vm_fixture(&[(
+            "SimpleFunction",
+            include_str!("../08/SimpleFunction/SimpleFunction.vm"),
+        )]);
+        let options = TranslateOptions {
+            bootstrap: Bootstrap::Force,
+            bootstrap_setup: BootstrapSetup {
+                entry_function: String::from("SimpleFunction.test"),
+                entry_nargs: 2,
+                sp: 312,
+                lcl: Some(305),
+                arg: Some(300),
+                this: Some(3010),
+                that: Some(4010),
+            },
+            ..TranslateOptions::default()
+        };
+        let asm_code = translate_program_with_options(vm_files, "SimpleFunction", &options)
+            .expect("Should be valid");
+        let mut hack_cpu = HackCpu::from_asm(&asm_code, "SimpleFunction").expect("Should be valid");
+        hack_cpu.set_ram(310, 1234);
+        hack_cpu.set_ram(311, 37);
+        hack_cpu.run(1000);
+
+        assert!(hack_cpu.is_idle());
+        assert_eq!(
+            [311, 305, 300, 3010, 4010, 1196],
+            [0, 1, 2, 3, 4, 310].map(|address| hack_cpu.ram(address))
+        );
+    }
+
+    #[test]
+    fn translate_without_unreachable_functions() {
+        let vm_files = parse_vm_fixture(&[
+            ("Sys", include_str!("../08/FibonacciElement/Sys.vm")),
+            ("Main", include_str!("../08/FibonacciElement/Main.vm")),
+        ]);
+        let unused_file = parse_vm_fixture(&[(
+            "Math",
+            "function Math.multiply 1\npush argument 0\npush argument 1\neq\nreturn",
+        )]);
+        let options = TranslateOptions {
+            remove_unreachable_functions: true,
+            ..TranslateOptions::default()
+        };
+
+        let asm_code =
+            translate_program(vm_files.clone(), "FibonacciElement").expect("Should be valid");
+        let mut all_vm_files = vm_files.clone();
+        all_vm_files.extend(unused_file);
+        let shaken_asm_code =
+            translate_program_with_options(all_vm_files.clone(), "FibonacciElement", &options)
+                .expect("Should be valid");
+        assert_eq!(asm_code, shaken_asm_code);
+
+        // without bootstrap code there is nothing to start from unless roots are given
+        let options = TranslateOptions {
+            bootstrap: Bootstrap::Suppress,
+            ..options
+        };
+        let kept_asm_code =
+            translate_program_with_options(all_vm_files.clone(), "FibonacciElement", &options)
+                .expect("Should be valid");
+        assert!(kept_asm_code.contains("(Math.multiply)"));
+        let options = TranslateOptions {
+            root_functions: vec![String::from("Math.multiply")],
+            ..options
+        };
+        let rooted_asm_code =
+            translate_program_with_options(all_vm_files.clone(), "FibonacciElement", &options)
+                .expect("Should be valid");
+        assert!(rooted_asm_code.contains("(Math.multiply)"));
+        assert!(!rooted_asm_code.contains("(Sys.init)"));
+
+        // roots are kept as well as the entry function the bootstrap calls
+        let options = TranslateOptions {
+            bootstrap: Bootstrap::Auto,
+            root_functions: vec![String::from("Main.fibonacci")],
+            ..options
+        };
+        let rooted_asm_code =
+            translate_program_with_options(all_vm_files.clone(), "FibonacciElement", &options)
+                .expect("Should be valid");
+        assert!(rooted_asm_code.contains("(Sys.init)"));
+        assert!(!rooted_asm_code.contains("(Math.multiply)"));
+        let options = TranslateOptions {
+            root_functions: vec![String::from("Typo.name")],
+            ..options
+        };
+        let diagnostics =
+            translate_program_with_options(all_vm_files, "FibonacciElement", &options)
+                .expect_err("Should be invalid");
+        assert!(matches!(
+            diagnostics.iter().next(),
+            Some(TranslateError::UndefinedFunction { name, .. }) if name == "Typo.name"
+        ));
+    }
+
+    #[test]
+    fn parse_spans_and_all_errors() {
+        let command_symbol_table = get_command_symbol_table();
+        let test_parser = VmCodeParser::new();
+        let input =
+            "// header\nfunction Foo.bar 0\n\tpush constant 1 // one\n\n  yo mama\npop constant 3";
+        let parsed_commands = test_parser
+            .parse(
+                "function Foo.bar 0\n\n\tpush constant 1 // one",
+                "Foo.vm",
+                &command_symbol_table,
+            )
+            .expect("Should be valid");
+        let diagnostics = test_parser
+            .parse(input, "Foo.vm", &command_symbol_table)
+            .expect_err("Should be invalid");
+
+        assert_eq!(
+            Span {
+                file: "Foo.vm".to_string(),
+                line: 3,
+                column: 2,
+                length: 15,
+                source_line: "\tpush constant 1 // one".to_string(),
+            },
+            parsed_commands[1].span
+        );
+        // both bad lines are reported, not just the first one
+        let lines: Vec<(usize, usize)> = diagnostics
+            .iter()
+            .filter_map(|error| error.span())
+            .map(|span| (span.line, span.column))
+            .collect();
+        assert_eq!(vec![(5, 3), (6, 1)], lines);
+    }
 }
@@ -0,0 +1,188 @@
+use crate::{Diagnostics, Span, TranslateError, VmCommand, VmFile};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+// a label that no goto or if-goto of its function jumps to, the code after it
+// can still run by falling through so this is only worth a warning
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnreachableLabel {
+    pub label: String,
+    pub function: String,
+    pub span: Span,
+}
+
+impl fmt::Display for UnreachableLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.function.is_empty() {
+            write!(f, "warning: label `{}` is never jumped to", self.label)?;
+        } else {
+            write!(
+                f,
+                "warning: label `{}` in `{}` is never jumped to",
+                self.label, self.function
+            )?;
+        }
+        self.span.fmt_snippet(f)
+    }
+}
+
+// the result of resolving every call and jump of a program
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    pub errors: Diagnostics,
+    pub unreachable_labels: Vec<UnreachableLabel>,
+}
+
+// resolves a whole program like a linker would: every call needs exactly one function
+// of that name in any file and every goto and if-goto exactly one label of that name
+// in the same function, labels outside of any function share one scope
+pub fn link_vm_files(vm_files: &[VmFile]) -> LinkReport {
+    let mut report = LinkReport::default();
+    let mut functions: HashMap<&str, &Span> = HashMap::new();
+    // (function, label) in order of declaration, so warnings come out in source order
+    let mut labels: Vec<(&str, &str, &Span)> = Vec::new();
+    let mut declared_labels: HashSet<(&str, &str)> = HashSet::new();
+    let mut jumps: Vec<(&str, &str, &Span)> = Vec::new();
+    let mut calls: Vec<(&str, &Span)> = Vec::new();
+
+    for vm_file in vm_files {
+        let mut current_function = "";
+        for parsed_command in &vm_file.commands {
+            let span = &parsed_command.span;
+            match &parsed_command.command {
+                VmCommand::Function { name, .. } => {
+                    current_function = name;
+                    if functions.insert(name, span).is_some() {
+                        report.errors.push(TranslateError::DuplicateFunction {
+                            name: name.clone(),
+                            span: span.clone(),
+                        });
+                    }
+                }
+                VmCommand::Label(label) => {
+                    if declared_labels.insert((current_function, label)) {
+                        labels.push((current_function, label, span));
+                    } else {
+                        report.errors.push(TranslateError::DuplicateLabel {
+                            label: label.clone(),
+                            span: span.clone(),
+                        });
+                    }
+                }
+                VmCommand::Goto(label) | VmCommand::IfGoto(label) => {
+                    jumps.push((current_function, label, span));
+                }
+                VmCommand::Call { name, .. } => calls.push((name, span)),
+                _ => {}
+            }
+        }
+    }
+
+    for &(name, span) in &calls {
+        if !functions.contains_key(name) {
+            report.errors.push(TranslateError::UndefinedFunction {
+                name: name.to_string(),
+                span: span.clone(),
+            });
+        }
+    }
+    let mut jump_targets: HashSet<(&str, &str)> = HashSet::new();
+    for &(function, label, span) in &jumps {
+        if declared_labels.contains(&(function, label)) {
+            jump_targets.insert((function, label));
+        } else {
+            report.errors.push(TranslateError::UndefinedLabel {
+                label: label.to_string(),
+                span: span.clone(),
+            });
+        }
+    }
+    report.unreachable_labels = labels
+        .into_iter()
+        .filter(|(function, label, _)| !jump_targets.contains(&(*function, *label)))
+        .map(|(function, label, span)| UnreachableLabel {
+            label: label.to_string(),
+            function: function.to_string(),
+            span: span.clone(),
+        })
+        .collect();
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_vm_fixture;
+
+    #[test]
+    fn link_program_fixtures() {
+        let fixtures: [&[(&str, &str)]; 4] = [
+            &[("BasicLoop", include_str!("../08/BasicLoop/BasicLoop.vm"))],
+            &[
+                ("Sys", include_str!("../08/FibonacciElement/Sys.vm")),
+                ("Main", include_str!("../08/FibonacciElement/Main.vm")),
+            ],
+            &[("Sys", include_str!("../08/NestedCall/Sys.vm"))],
+            &[
+                ("Sys", include_str!("../08/StaticsTest/Sys.vm")),
+                ("Class1", include_str!("../08/StaticsTest/Class1.vm")),
+                ("Class2", include_str!("../08/StaticsTest/Class2.vm")),
+            ],
+        ];
+        for vm_files in fixtures {
+            let report = link_vm_files(&parse_vm_fixture(vm_files));
+            assert!(report.errors.is_empty(), "{}", report.errors);
+        }
+    }
+
+    #[test]
+    fn link_reports_every_problem() {
+        let main_code = "function Main.main 0\ncall Main.missing 0\nlabel LOOP\ngoto LOOP\ngoto END\nlabel UNUSED\nlabel LOOP\nfunction Main.other 0\ngoto LOOP\nlabel LOOP\nreturn";
+        let other_code = "function Main.main 0\ncall Main.other 0\nreturn";
+        let report = link_vm_files(&parse_vm_fixture(&[
+            ("Main", main_code),
+            ("Other", other_code),
+        ]));
+
+        let errors: Vec<(String, usize)> = report
+            .errors
+            .iter()
+            .map(|error| {
+                let message = error.to_string();
+                let message = message.lines().next().unwrap().to_string();
+                (message, error.span().unwrap().line)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (String::from("error: duplicate label `LOOP`"), 7),
+                (String::from("error: duplicate function `Main.main`"), 1),
+                (String::from("error: undefined function `Main.missing`"), 2),
+                (String::from("error: undefined label `END`"), 5),
+            ],
+            errors
+        );
+        // the jump to LOOP in Main.other only reaches the LOOP of Main.other
+        assert_eq!(
+            vec![UnreachableLabel {
+                label: String::from("UNUSED"),
+                function: String::from("Main.main"),
+                span: Span {
+                    file: String::from("Main"),
+                    line: 6,
+                    column: 1,
+                    length: 12,
+                    source_line: String::from("label UNUSED"),
+                },
+            }],
+            report.unreachable_labels
+        );
+        assert_eq!(
+            "warning: label `UNUSED` in `Main.main` is never jumped to\n --> Main:6:1\n  |\n6 | label UNUSED\n  | ^^^^^^^^^^^^",
+            report.unreachable_labels[0].to_string()
+        );
+    }
+}
@@ -1,21 +1,415 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::fs::{self, File};
-use std::io::{LineWriter, Write};
+use std::fs;
+use std::io::{self, Write};
 use std::path::PathBuf;
-use std::{env, path::Path};
-use vm_translator::{VMCommandType, VmCodeParser, VmCodeWriter};
+use std::{env, path::Path, process};
+use vm_translator::{
+    get_command_symbol_table, link_vm_files, optimize_vm_files, remove_unreachable_functions,
+    translate_program_with_options, write_ir, write_json, Bootstrap, CallGraph, Diagnostics,
+    HackAssembler, Span, StreamTranslator, TranslateError, TranslateOptions, VmCodeParser,
+    VmCommand, VmFile,
+};
 
 // nand2tetris project 7 and 8 vm_translator source code
 // usage:
 // pass in the path of a *.vm file as an argument e.g. ./vm_translator myVMFile.vm or
 // pass in a directory containing 1 or more *.vm files as an argument e.g. ./vm_translator myVMDirectory
 // it will output a myVmFile.asm file or myVMDirectory.asm
+// every input is translated as its own program, run with --help for all options
 // use this for project 7 and 8 requirements
 
-fn get_valid_vm_files<P: AsRef<Path>>(file_path: P) -> Vec<PathBuf> {
+const HELP: &str = "\
+usage: vm_translator [options] <input>...
+       vm_translator callgraph [--format dot|json] [options] <input>...
+
+each input is a *.vm file or a directory of *.vm files translated as one program,
+subdirectories are only looked at with --recursive,
+the output is written next to it as <input>.asm unless -o is given
+
+callgraph writes the static call graph of each program to <input>.dot instead,
+with the number of call sites and arguments of every call, recursive functions
+are marked and functions that are called but not declared are listed too
+
+options:
+  --format <format>     dot   graphviz call graph (default), only for callgraph
+                        json  the same call graph as json
+  -o, --output <path>   write the output to <path>, - writes it to stdout
+                        only one input can be written to a file path
+  --emit <format>       asm   hack assembly (default)
+                        hack  binary machine code from the built-in assembler
+                        both  the assembly and the machine code next to each other
+                        ir    the vm commands that get translated, after -O2 passes,
+                              a push forwarded into the next pop still shows as both
+                        json  the same commands as json with their source lines
+  --order <names>       translate these comma separated files first, in this order,
+                        the rest follow sorted by path with Sys.vm first
+  -r, --recursive       translate every directory below a directory input that holds
+                        *.vm files as its own program, written to <directory>.asm
+  --include <glob>      only translate the *.vm files of a directory input that match,
+                        can be given more than once
+  --exclude <glob>      skip the *.vm files of a directory input that match, can be
+                        given more than once, globs support * ? and **, a glob with a /
+                        matches the path below the input, otherwise the file name
+  --batch               translate every leaf directory below a directory input that
+                        holds *.vm files as its own program into
+                        <directory>/<directory>.asm, keep going after failed programs
+                        and print a summary table
+  --force-bootstrap     start with SP=256 and call Sys.init even without Sys.vm
+  --no-bootstrap        never emit bootstrap code, the program ends in an endless loop
+  --entry <function>    call <function> from the bootstrap instead of Sys.init,
+                        the program stops when it returns
+  --entry-args <n>      number of arguments of the entry function already on the stack
+  --sp <address>        initial SP set by the bootstrap (default 256)
+  --lcl, --arg, --this, --that <address>
+                        also set that pointer before calling the entry function
+  -O, -O1               run the peephole optimizer over the generated assembly
+  -O2                   also fold constants and forward pushes into pops, values popped
+                        into temp registers no vm code reads are dropped so temp can end
+                        up with older values
+  -O0                   no optimization (default)
+  --compact             share one routine for all comparisons, calls and returns
+  --tree-shake          leave out every function the entry function of the bootstrap
+                        can never call, -v also prints how many instructions that saved
+  --root <function>     tree shake starting from <function> as well as the entry
+                        function, can be given more than once
+  --stream              translate line by line without reading whole files into memory,
+                        only for assembly output without -O or --tree-shake, a program
+                        with errors leaves a partial output
+  --no-link-check       do not check that every call and jump has exactly one target,
+                        --stream never checks them
+  -q, --quiet           only print errors and no warnings
+  -v, --verbose         also print every parsed file and instruction counts
+  -h, --help            print this help
+";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EmitFormat {
+    Asm,
+    Hack,
+    Both,
+    Ir,
+    Json,
+}
+
+impl EmitFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            EmitFormat::Asm | EmitFormat::Both => "asm",
+            EmitFormat::Hack => "hack",
+            EmitFormat::Ir => "ir",
+            EmitFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug)]
+struct CliOptions {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    emit_format: EmitFormat,
+    translate_options: TranslateOptions,
+    verbosity: Verbosity,
+    file_order: Vec<String>,
+    recursive: bool,
+    batch: bool,
+    stream: bool,
+    link_check: bool,
+    file_filter: FileFilter,
+    // the callgraph subcommand writes call graphs instead of translating
+    graph_format: Option<GraphFormat>,
+}
+
+// one line of the --batch summary, instructions is None when the program failed
+#[derive(Debug, Eq, PartialEq)]
+struct BatchResult {
+    program: PathBuf,
+    instructions: Option<usize>,
+}
+
+#[derive(Debug)]
+enum CliCommand {
+    Help,
+    Translate(Box<CliOptions>),
+}
+
+fn invalid_arguments(message: &str) -> TranslateError {
+    TranslateError::InvalidArguments(format!("{message} Run with --help for usage."))
+}
+
+// the value after a flag that takes a number such as --sp 256
+fn next_number<'a>(
+    args_iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<u16, TranslateError> {
+    args_iter
+        .next()
+        .and_then(|value| value.parse::<u16>().ok())
+        .filter(|&value| value <= 32767)
+        .ok_or_else(|| invalid_arguments(&format!("Please pass a number up to 32767 to {flag}.")))
+}
+
+// args[0] is the program path
+fn parse_args(args: &[String]) -> Result<CliCommand, TranslateError> {
+    let mut cli_options = CliOptions {
+        inputs: Vec::new(),
+        output: None,
+        emit_format: EmitFormat::Asm,
+        translate_options: TranslateOptions::default(),
+        verbosity: Verbosity::Normal,
+        file_order: Vec::new(),
+        recursive: false,
+        batch: false,
+        stream: false,
+        link_check: true,
+        file_filter: FileFilter::default(),
+        graph_format: None,
+    };
+    let mut args_iter = args.iter().skip(1).peekable();
+    if args_iter.next_if(|arg| *arg == "callgraph").is_some() {
+        cli_options.graph_format = Some(GraphFormat::Dot);
+    }
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-o" | "--output" => {
+                let output = args_iter
+                    .next()
+                    .ok_or_else(|| invalid_arguments("Please pass a path to --output."))?;
+                cli_options.output = Some(PathBuf::from(output));
+            }
+            "--emit" => {
+                cli_options.emit_format = match args_iter.next().map(String::as_str) {
+                    Some("asm") => EmitFormat::Asm,
+                    Some("hack") => EmitFormat::Hack,
+                    Some("both") => EmitFormat::Both,
+                    Some("ir") => EmitFormat::Ir,
+                    Some("json") => EmitFormat::Json,
+                    _ => {
+                        return Err(invalid_arguments(
+                            "Please pass one of asm, hack, both, ir or json to --emit.",
+                        ))
+                    }
+                };
+            }
+            "--format" => {
+                let graph_format = match args_iter.next().map(String::as_str) {
+                    Some("dot") => GraphFormat::Dot,
+                    Some("json") => GraphFormat::Json,
+                    _ => {
+                        return Err(invalid_arguments(
+                            "Please pass one of dot or json to --format.",
+                        ))
+                    }
+                };
+                if cli_options.graph_format.is_none() {
+                    return Err(invalid_arguments("--format is only for callgraph."));
+                }
+                cli_options.graph_format = Some(graph_format);
+            }
+            "--order" => {
+                let file_order = args_iter.next().ok_or_else(|| {
+                    invalid_arguments("Please pass comma separated file names to --order.")
+                })?;
+                cli_options.file_order = file_order
+                    .split(',')
+                    .map(|name| name.trim().trim_end_matches(".vm").to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+            }
+            "-r" | "--recursive" => cli_options.recursive = true,
+            "--batch" => cli_options.batch = true,
+            "--stream" => cli_options.stream = true,
+            "--no-link-check" => cli_options.link_check = false,
+            "--tree-shake" => cli_options.translate_options.remove_unreachable_functions = true,
+            "--root" => {
+                let root_function = args_iter
+                    .next()
+                    .ok_or_else(|| invalid_arguments("Please pass a function name to --root."))?;
+                cli_options.translate_options.remove_unreachable_functions = true;
+                cli_options
+                    .translate_options
+                    .root_functions
+                    .push(root_function.clone());
+            }
+            "--include" | "--exclude" => {
+                let pattern = args_iter
+                    .next()
+                    .ok_or_else(|| invalid_arguments(&format!("Please pass a glob to {arg}.")))?;
+                if arg == "--include" {
+                    cli_options.file_filter.include.push(pattern.clone());
+                } else {
+                    cli_options.file_filter.exclude.push(pattern.clone());
+                }
+            }
+            "--force-bootstrap" => cli_options.translate_options.bootstrap = Bootstrap::Force,
+            "--no-bootstrap" => cli_options.translate_options.bootstrap = Bootstrap::Suppress,
+            "--entry" => {
+                let entry_function = args_iter
+                    .next()
+                    .ok_or_else(|| invalid_arguments("Please pass a function name to --entry."))?;
+                cli_options.translate_options.bootstrap_setup.entry_function =
+                    entry_function.clone();
+            }
+            "--entry-args" => {
+                cli_options.translate_options.bootstrap_setup.entry_nargs =
+                    next_number(&mut args_iter, arg)?;
+            }
+            "--sp" => {
+                cli_options.translate_options.bootstrap_setup.sp =
+                    next_number(&mut args_iter, arg)?;
+            }
+            "--lcl" | "--arg" | "--this" | "--that" => {
+                let value = Some(next_number(&mut args_iter, arg)?);
+                let bootstrap_setup = &mut cli_options.translate_options.bootstrap_setup;
+                match arg.as_str() {
+                    "--lcl" => bootstrap_setup.lcl = value,
+                    "--arg" => bootstrap_setup.arg = value,
+                    "--this" => bootstrap_setup.this = value,
+                    _ => bootstrap_setup.that = value,
+                }
+            }
+            "-O" | "-O1" => cli_options.translate_options.optimization_level = 1,
+            "-O0" => cli_options.translate_options.optimization_level = 0,
+            "-O2" => cli_options.translate_options.optimization_level = 2,
+            "--compact" => cli_options.translate_options.compact = true,
+            "-q" | "--quiet" => cli_options.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => cli_options.verbosity = Verbosity::Verbose,
+            "-" => {
+                return Err(invalid_arguments(
+                    "Reading vm code from stdin is not supported, please pass a file or directory.",
+                ));
+            }
+            flag if flag.starts_with('-') => {
+                return Err(invalid_arguments(&format!("Unknown option {flag}.")));
+            }
+            input => cli_options.inputs.push(PathBuf::from(input)),
+        }
+    }
+
+    if cli_options.inputs.is_empty() {
+        return Err(invalid_arguments(
+            "Please enter a file path as an argument to the program.",
+        ));
+    }
+    let writes_to_file = cli_options
+        .output
+        .as_ref()
+        .is_some_and(|output| output != Path::new("-"));
+    if writes_to_file && cli_options.inputs.len() > 1 {
+        return Err(invalid_arguments(
+            "Please pass a single input when writing to an --output file.",
+        ));
+    }
+    if cli_options.stream
+        && (cli_options.emit_format != EmitFormat::Asm
+            || cli_options.translate_options.optimization_level > 0
+            || cli_options.translate_options.remove_unreachable_functions
+            || cli_options.batch)
+    {
+        return Err(invalid_arguments(
+            "--stream only writes assembly without -O or --tree-shake and can not be used with --batch.",
+        ));
+    }
+    if cli_options.graph_format.is_some() && (cli_options.batch || cli_options.stream) {
+        return Err(invalid_arguments(
+            "callgraph can not be used with --batch or --stream.",
+        ));
+    }
+    if cli_options.batch && cli_options.output.is_some() {
+        return Err(invalid_arguments(
+            "--batch writes every program next to its vm files and can not take --output.",
+        ));
+    }
+
+    Ok(CliCommand::Translate(Box::new(cli_options)))
+}
+
+// which files of a directory input get translated
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct FileFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl FileFilter {
+    // patterns with a / match the path relative to the input, others just the file name
+    fn matches(&self, relative_path: &Path) -> bool {
+        let path = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let file_name = relative_path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let pattern_matches = |pattern: &String| {
+            if pattern.contains('/') {
+                glob_match(pattern, &path)
+            } else {
+                glob_match(pattern, &file_name)
+            }
+        };
+
+        (self.include.is_empty() || self.include.iter().any(pattern_matches))
+            && !self.exclude.iter().any(pattern_matches)
+    }
+}
+
+// * matches within one path component, ** across components and ? a single character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_chars(&pattern, &text)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        // **/ matches whole directories only, so the rest starts at a component
+        ['*', '*', '/', rest @ ..] => (0..=text.len())
+            .filter(|&start| start == 0 || text[start - 1] == '/')
+            .any(|start| glob_match_chars(rest, &text[start..])),
+        ['*', '*', rest @ ..] => {
+            (0..=text.len()).any(|start| glob_match_chars(rest, &text[start..]))
+        }
+        ['*', rest @ ..] => {
+            let component_end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=component_end).any(|start| glob_match_chars(rest, &text[start..]))
+        }
+        ['?', rest @ ..] => text
+            .split_first()
+            .is_some_and(|(&c, text)| c != '/' && glob_match_chars(rest, text)),
+        [c, rest @ ..] => text
+            .split_first()
+            .is_some_and(|(t, text)| t == c && glob_match_chars(rest, text)),
+    }
+}
+
+// the *.vm files directly inside a program directory, a file input is taken as it is
+fn get_valid_vm_files(file_path: &Path, input: &Path, file_filter: &FileFilter) -> Vec<PathBuf> {
     let mut paths_vec: Vec<PathBuf> = Vec::new();
-    let file_path = file_path.as_ref();
 
     if file_path.is_file() && file_path.extension().is_some_and(|ext| ext == "vm") {
         paths_vec.push(file_path.to_path_buf());
@@ -23,143 +417,1015 @@ fn get_valid_vm_files<P: AsRef<Path>>(file_path: P) -> Vec<PathBuf> {
         if let Ok(file_dir) = file_path.read_dir() {
             for file_entry in file_dir.flatten() {
                 let valid_file_path = file_entry.path();
+                let relative_path = valid_file_path
+                    .strip_prefix(input)
+                    .unwrap_or(&valid_file_path);
                 if valid_file_path.is_file()
                     && valid_file_path.extension().is_some_and(|ext| ext == "vm")
+                    && file_filter.matches(relative_path)
                 {
                     paths_vec.push(valid_file_path);
-                } else if valid_file_path.is_dir() {
-                    paths_vec.extend(get_valid_vm_files(valid_file_path));
                 }
             }
         }
     }
 
     paths_vec
+}
 
-    //Err("Please enter a file path that is of *.vm or a directory containing 1 or more *.vm files to the program.".to_string())?
+// every program an input stands for, with --recursive each directory below a directory
+// input that holds *.vm files is a program of its own, with --batch only the leaf ones
+fn get_programs(input: &Path, cli_options: &CliOptions) -> Vec<PathBuf> {
+    if !(cli_options.recursive || cli_options.batch) || !input.is_dir() {
+        return vec![input.to_path_buf()];
+    }
+
+    let mut programs = Vec::new();
+    collect_programs(input, input, cli_options, &mut programs);
+    programs.sort();
+
+    programs
 }
 
-fn check_valid_vm_files(args: &[String]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-    // validate there was an argument passed
-    if args.len() != 2 {
-        Err("Please enter a file path as an argument to the program.".to_string())?
+// whether directory or any directory below it is a program
+fn collect_programs(
+    directory: &Path,
+    input: &Path,
+    cli_options: &CliOptions,
+    programs: &mut Vec<PathBuf>,
+) -> bool {
+    let mut holds_programs = false;
+    if let Ok(file_dir) = directory.read_dir() {
+        // symlinks are not followed, one to a parent directory would never end
+        let subdirectories = file_dir.flatten().filter(|file_entry| {
+            file_entry
+                .file_type()
+                .is_ok_and(|file_type| file_type.is_dir())
+        });
+        for file_entry in subdirectories {
+            holds_programs |= collect_programs(&file_entry.path(), input, cli_options, programs);
+        }
+    }
+    if holds_programs && cli_options.batch {
+        return true;
+    }
+
+    let is_program = !get_valid_vm_files(directory, input, &cli_options.file_filter).is_empty();
+    if is_program {
+        programs.push(directory.to_path_buf());
     }
+    holds_programs || is_program
+}
 
+fn check_valid_vm_files(
+    program: &Path,
+    input: &Path,
+    cli_options: &CliOptions,
+) -> Result<Vec<PathBuf>, TranslateError> {
     // validate to see whether there are vm files
-    let mut vm_files_vec = get_valid_vm_files(Path::new(&args[1]));
+    let vm_files_vec = get_valid_vm_files(program, input, &cli_options.file_filter);
     if vm_files_vec.is_empty() {
-        Err("Please ensure the file path entered has files of extension type *.vm".to_string())?
-    } else if let Some(sys_vm_index) = vm_files_vec.iter().position(|x| x.ends_with("Sys.vm")) {
-        // reorder vec so that sys is always first if applicable
-        let sys_vm_file = vm_files_vec.remove(sys_vm_index);
-        vm_files_vec.insert(0, sys_vm_file);
+        return Err(TranslateError::NoVmFiles {
+            path: program.to_path_buf(),
+        });
     }
 
+    order_vm_files(vm_files_vec, &cli_options.file_order)
+}
+
+// read_dir order depends on the file system, so files are sorted by path with Sys.vm first
+// any file stems given in file_order come before all others, in that order
+fn order_vm_files(
+    mut vm_files_vec: Vec<PathBuf>,
+    file_order: &[String],
+) -> Result<Vec<PathBuf>, TranslateError> {
+    let file_stem = |path: &PathBuf| {
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    if let Some(missing_file) = file_order
+        .iter()
+        .find(|name| !vm_files_vec.iter().any(|path| file_stem(path) == **name))
+    {
+        return Err(TranslateError::InvalidArguments(format!(
+            "Please only pass names of translated files to --order, there is no {missing_file}.vm."
+        )));
+    }
+
+    vm_files_vec.sort_by_key(|path| {
+        let stem = file_stem(path);
+        let position = file_order
+            .iter()
+            .position(|name| *name == stem)
+            .unwrap_or(file_order.len());
+        (position, stem != "Sys", path.clone())
+    });
+
     Ok(vm_files_vec)
 }
 
-fn get_command_symbol_table() -> HashMap<VMCommandType, Vec<&'static str>> {
-    let mut command_symbol_table: HashMap<VMCommandType, Vec<&str>> = HashMap::new();
-    command_symbol_table.insert(
-        VMCommandType::Carithmetic,
-        vec!["add", "sub", "neg", "eq", "gt", "lt", "and", "or", "not"],
-    );
-    command_symbol_table.insert(
-        VMCommandType::Cpush,
-        vec![
-            "constant", "local", "argument", "this", "that", "static", "temp", "pointer",
-        ],
-    );
-    command_symbol_table.insert(
-        VMCommandType::Cpop,
-        vec![
-            "local", "argument", "this", "that", "static", "temp", "pointer",
-        ],
-    );
-    command_symbol_table
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    let vm_files_vec = check_valid_vm_files(&args)?;
+fn parse_vm_files(
+    vm_files_vec: Vec<PathBuf>,
+    verbosity: Verbosity,
+) -> Result<Vec<VmFile>, Diagnostics> {
     let command_symbol_table = get_command_symbol_table();
-    let asm_file_path = Path::new(&args[1]);
-    let output_asm_file = File::create(asm_file_path.with_extension("asm"))?;
-    let mut output_asm_file = LineWriter::new(output_asm_file);
-
-    // to track function call sequence
-    let mut function_call_stack: Vec<String> = Vec::new();
-    let mut bootstrap_code_exists = false;
+    let vm_code_parser = VmCodeParser::new();
 
+    // parse every file first so that all errors are reported at once
+    let mut parsed_vm_files = Vec::new();
+    let mut diagnostics = Diagnostics::new();
     for vm_file in vm_files_vec {
-        let vm_file_name_no_extension = vm_file
-            .as_path()
-            .file_stem()
-            .expect("Should be valid")
-            .to_str()
-            .expect("Should be valid");
-        let contents = fs::read_to_string(&vm_file)?;
-        let vm_code_parser = VmCodeParser::new();
-        let cleaned_contents = vm_code_parser.clean_vm_code(contents);
-        let vm_code_writer = VmCodeWriter::new(vm_code_parser, cleaned_contents);
-        if vm_file_name_no_extension == "Sys" {
-            // bootstrap code required
-            let init_code = String::from("call Sys.init 0");
-            let init_code_parser = VmCodeParser::new();
-            let cleaned_init_code = init_code_parser.clean_vm_code(init_code); // this is useless...but to stay consistent
-            let init_code_writer = VmCodeWriter::new(init_code_parser, cleaned_init_code);
-            let init_vm_code = init_code_writer.write_init();
-            output_asm_file.write_all(init_vm_code.as_bytes())?;
-            let translated_vm_code = init_code_writer.translate(
-                &command_symbol_table,
-                asm_file_path
+        let contents = fs::read_to_string(&vm_file).map_err(|source| TranslateError::Io {
+            path: vm_file.clone(),
+            source,
+        })?;
+        match vm_code_parser.parse(
+            &contents,
+            &vm_file.display().to_string(),
+            &command_symbol_table,
+        ) {
+            Ok(vm_commands) => {
+                if verbosity >= Verbosity::Verbose {
+                    eprintln!(
+                        "parsed {} ({} commands)",
+                        vm_file.display(),
+                        vm_commands.len()
+                    );
+                }
+                let vm_file_name_no_extension = vm_file
+                    .as_path()
                     .file_stem()
                     .expect("Should be valid")
                     .to_str()
-                    .expect("Should be valid"),
-                &mut function_call_stack,
-            )?;
-            output_asm_file.write_all(translated_vm_code.as_bytes())?;
-            bootstrap_code_exists = true;
+                    .expect("Should be valid");
+                parsed_vm_files.push(VmFile::new(vm_file_name_no_extension, vm_commands));
+            }
+            Err(file_diagnostics) => diagnostics.extend(file_diagnostics),
         }
-        let translated_vm_code: String = vm_code_writer.translate(
-            &command_symbol_table,
-            vm_file_name_no_extension,
-            &mut function_call_stack,
-        )?;
-        output_asm_file.write_all(translated_vm_code.as_bytes())?;
     }
 
-    if !bootstrap_code_exists {
-        // set end of file
-        output_asm_file.write_all("(end_asm_file)\n@end_asm_file\n0;JMP".as_bytes())?;
+    if diagnostics.is_empty() {
+        Ok(parsed_vm_files)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+// undefined or duplicate functions and labels are errors, unreachable labels only warnings
+fn check_links(vm_files: &[VmFile], cli_options: &CliOptions) -> Result<(), Diagnostics> {
+    let mut link_report = link_vm_files(vm_files);
+    // the bootstrap calls the entry function too
+    let bootstrap_setup = &cli_options.translate_options.bootstrap_setup;
+    let entry_function_exists = vm_files
+        .iter()
+        .flat_map(|vm_file| &vm_file.commands)
+        .any(|parsed_command| {
+            matches!(&parsed_command.command, VmCommand::Function { name, .. } if *name == bootstrap_setup.entry_function)
+        });
+    if cli_options.translate_options.emits_bootstrap(vm_files) && !entry_function_exists {
+        link_report.errors.push(TranslateError::UndefinedFunction {
+            name: bootstrap_setup.entry_function.clone(),
+            span: Span::default(),
+        });
+    }
+
+    if cli_options.verbosity >= Verbosity::Normal {
+        for unreachable_label in &link_report.unreachable_labels {
+            eprintln!("{unreachable_label}\n");
+        }
     }
+    if link_report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(link_report.errors)
+    }
+}
+
+fn instruction_count(asm_code: &str) -> usize {
+    asm_code
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('('))
+        .count()
+}
+
+// with all_vm_files, which only -v passes, the program is translated once more without
+// tree shaking to tell how many instructions leaving those functions out saved
+fn report_unreachable_functions(
+    removed_functions: &[String],
+    all_vm_files: Option<Vec<VmFile>>,
+    program_name: &str,
+    translate_options: &TranslateOptions,
+    output_asm: &str,
+) -> Result<(), Diagnostics> {
+    let removed = match removed_functions.len() {
+        1 => String::from("1 unreachable function"),
+        count => format!("{count} unreachable functions"),
+    };
+    let Some(all_vm_files) = all_vm_files else {
+        eprintln!("removed {removed} from {program_name}");
+        return Ok(());
+    };
+
+    let options_keeping_all = TranslateOptions {
+        remove_unreachable_functions: false,
+        ..translate_options.clone()
+    };
+    let all_asm = translate_program_with_options(all_vm_files, program_name, &options_keeping_all)?;
+    let saved_instructions = instruction_count(&all_asm) - instruction_count(output_asm);
+    eprintln!("removed {removed} from {program_name}, saving {saved_instructions} instructions");
 
     Ok(())
 }
 
+fn write_output(output_path: &Path, contents: &str) -> Result<(), TranslateError> {
+    let result = if output_path == Path::new("-") {
+        io::stdout().lock().write_all(contents.as_bytes())
+    } else {
+        fs::write(output_path, contents)
+    };
+    result.map_err(|source| TranslateError::Io {
+        path: output_path.to_path_buf(),
+        source,
+    })
+}
+
+// translates every program of one input into every output its emit format asks for
+fn translate_input(input: &Path, cli_options: &CliOptions) -> Result<(), Diagnostics> {
+    let programs = get_programs(input, cli_options);
+    if programs.is_empty() {
+        return Err(TranslateError::NoVmFiles {
+            path: input.to_path_buf(),
+        }
+        .into());
+    }
+    let writes_to_file = cli_options
+        .output
+        .as_ref()
+        .is_some_and(|output| output != Path::new("-"));
+    if writes_to_file && programs.len() > 1 {
+        return Err(invalid_arguments(&format!(
+            "{} holds {} programs, which can not share one --output file.",
+            input.display(),
+            programs.len()
+        ))
+        .into());
+    }
+
+    let mut diagnostics = Diagnostics::new();
+    for program in programs {
+        if cli_options.stream {
+            if let Err(program_diagnostics) = stream_program(&program, input, cli_options) {
+                diagnostics.extend(program_diagnostics);
+            }
+            continue;
+        }
+        let outputs = match build_outputs(&program, input, cli_options) {
+            Ok(outputs) => outputs,
+            Err(program_diagnostics) => {
+                diagnostics.extend(program_diagnostics);
+                continue;
+            }
+        };
+        for (output_path, contents) in outputs {
+            write_output(&output_path, &contents)?;
+            if cli_options.verbosity >= Verbosity::Normal && output_path != Path::new("-") {
+                eprintln!("wrote {}", output_path.display());
+            }
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+// translates one program straight from its files into the output without holding either
+// in memory, errors are only known at the end so a failed program leaves partial output
+fn stream_program(
+    program: &Path,
+    input: &Path,
+    cli_options: &CliOptions,
+) -> Result<(), Diagnostics> {
+    let vm_files_vec = check_valid_vm_files(program, input, cli_options)?;
+    let program_name = program
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Main");
+    let output_path = cli_options
+        .output
+        .clone()
+        .unwrap_or_else(|| program.with_extension("asm"));
+    let file_stems: Vec<&str> = vm_files_vec
+        .iter()
+        .map(|vm_file| {
+            vm_file
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .expect("Should be valid")
+        })
+        .collect();
+
+    let writer: Box<dyn Write> = if output_path == Path::new("-") {
+        Box::new(io::stdout().lock())
+    } else {
+        let output_file = fs::File::create(&output_path).map_err(|source| TranslateError::Io {
+            path: output_path.clone(),
+            source,
+        })?;
+        Box::new(output_file)
+    };
+    let mut stream_translator = StreamTranslator::new(
+        io::BufWriter::new(writer),
+        program_name,
+        &file_stems,
+        &cli_options.translate_options,
+    )?;
+    for (vm_file, file_stem) in vm_files_vec.iter().zip(&file_stems) {
+        let vm_file_reader = fs::File::open(vm_file).map_err(|source| TranslateError::Io {
+            path: vm_file.clone(),
+            source,
+        })?;
+        stream_translator.translate_file(
+            io::BufReader::new(vm_file_reader),
+            file_stem,
+            &vm_file.display().to_string(),
+        )?;
+    }
+    stream_translator.finish()?;
+
+    if cli_options.verbosity >= Verbosity::Normal && output_path != Path::new("-") {
+        eprintln!("wrote {}", output_path.display());
+    }
+    Ok(())
+}
+
+// the path and contents of every output of one program, nothing is written yet
+fn build_outputs(
+    program: &Path,
+    input: &Path,
+    cli_options: &CliOptions,
+) -> Result<Vec<(PathBuf, String)>, Diagnostics> {
+    let vm_files_vec = check_valid_vm_files(program, input, cli_options)?;
+    let parsed_vm_files = parse_vm_files(vm_files_vec, cli_options.verbosity)?;
+    let program_name = program
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Main");
+    // undeclared functions are part of the call graph rather than errors
+    if let Some(graph_format) = cli_options.graph_format {
+        let call_graph = CallGraph::new(&parsed_vm_files);
+        let output_path = cli_options
+            .output
+            .clone()
+            .unwrap_or_else(|| program.with_extension(graph_format.extension()));
+        let contents = match graph_format {
+            GraphFormat::Dot => call_graph.write_dot(program_name),
+            GraphFormat::Json => call_graph.write_json(program_name),
+        };
+        return Ok(vec![(output_path, contents)]);
+    }
+    if cli_options.link_check {
+        check_links(&parsed_vm_files, cli_options)?;
+    }
+    let translate_options = &cli_options.translate_options;
+    let emit_format = cli_options.emit_format;
+    // --batch keeps each output inside its program directory
+    let output_path = match &cli_options.output {
+        Some(output) => output.clone(),
+        None if cli_options.batch && program.is_dir() => program
+            .join(program_name)
+            .with_extension(emit_format.extension()),
+        None => program.with_extension(emit_format.extension()),
+    };
+
+    let mut outputs: Vec<(PathBuf, String)> = Vec::new();
+    match emit_format {
+        EmitFormat::Ir | EmitFormat::Json => {
+            let vm_files = match translate_options.reachability_roots(&parsed_vm_files)? {
+                Some(roots) => remove_unreachable_functions(parsed_vm_files, &roots).0,
+                None => parsed_vm_files,
+            };
+            let vm_files = if translate_options.optimization_level >= 2 {
+                optimize_vm_files(vm_files)
+            } else {
+                vm_files
+            };
+            let contents = if emit_format == EmitFormat::Ir {
+                write_ir(&vm_files)
+            } else {
+                write_json(&vm_files, program_name)
+            };
+            outputs.push((output_path, contents));
+        }
+        EmitFormat::Asm | EmitFormat::Hack | EmitFormat::Both => {
+            let removed_functions = match translate_options.reachability_roots(&parsed_vm_files)? {
+                Some(roots) if cli_options.verbosity >= Verbosity::Normal => {
+                    Some(remove_unreachable_functions(parsed_vm_files.clone(), &roots).1)
+                }
+                _ => None,
+            };
+            let all_vm_files = (removed_functions.is_some()
+                && cli_options.verbosity >= Verbosity::Verbose)
+                .then(|| parsed_vm_files.clone());
+            let output_asm =
+                translate_program_with_options(parsed_vm_files, program_name, translate_options)?;
+            if cli_options.verbosity >= Verbosity::Verbose {
+                eprintln!(
+                    "translated {program_name} into {} instructions",
+                    instruction_count(&output_asm)
+                );
+            }
+            if let Some(removed_functions) = removed_functions {
+                report_unreachable_functions(
+                    &removed_functions,
+                    all_vm_files,
+                    program_name,
+                    translate_options,
+                    &output_asm,
+                )?;
+            }
+
+            if emit_format == EmitFormat::Hack || emit_format == EmitFormat::Both {
+                let hack_path = if emit_format == EmitFormat::Both && output_path != Path::new("-")
+                {
+                    output_path.with_extension("hack")
+                } else {
+                    output_path.clone()
+                };
+                let hack_assembler = HackAssembler::new();
+                let output_hack = hack_assembler.assemble(
+                    &output_asm,
+                    &program.with_extension("asm").display().to_string(),
+                )?;
+                if emit_format == EmitFormat::Both {
+                    outputs.push((output_path, output_asm));
+                }
+                outputs.push((hack_path, output_hack));
+            } else {
+                outputs.push((output_path, output_asm));
+            }
+        }
+    }
+
+    Ok(outputs)
+}
+
+// translates every program of one input on its own, a failed program does not stop the rest
+fn translate_batch(
+    input: &Path,
+    cli_options: &CliOptions,
+    batch_results: &mut Vec<BatchResult>,
+) -> Result<(), Diagnostics> {
+    let programs = get_programs(input, cli_options);
+    if programs.is_empty() {
+        return Err(TranslateError::NoVmFiles {
+            path: input.to_path_buf(),
+        }
+        .into());
+    }
+
+    let mut diagnostics = Diagnostics::new();
+    for program in programs {
+        let written = build_outputs(&program, input, cli_options).and_then(|outputs| {
+            for (output_path, contents) in &outputs {
+                write_output(output_path, contents)?;
+            }
+            Ok(outputs)
+        });
+        let instructions = match written {
+            Ok(outputs) => Some(
+                outputs
+                    .iter()
+                    .map(|(output_path, contents)| {
+                        if output_path.extension().is_some_and(|ext| ext == "hack") {
+                            contents.lines().count()
+                        } else {
+                            instruction_count(contents)
+                        }
+                    })
+                    .max()
+                    .unwrap_or(0),
+            ),
+            Err(program_diagnostics) => {
+                diagnostics.extend(program_diagnostics);
+                None
+            }
+        };
+        batch_results.push(BatchResult {
+            program,
+            instructions,
+        });
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn format_batch_summary(batch_results: &[BatchResult]) -> String {
+    let program_width = batch_results
+        .iter()
+        .map(|batch_result| batch_result.program.display().to_string().len())
+        .chain([7])
+        .max()
+        .unwrap_or(0);
+    let mut summary = format!("{:<program_width$}  result  instructions\n", "program");
+    for batch_result in batch_results {
+        let (result, instructions) = match batch_result.instructions {
+            Some(instructions) => ("ok", instructions.to_string()),
+            None => ("failed", String::from("-")),
+        };
+        summary.push_str(&format!(
+            "{:<program_width$}  {result:<6}  {instructions:>12}\n",
+            batch_result.program.display()
+        ));
+    }
+    let failed = batch_results
+        .iter()
+        .filter(|batch_result| batch_result.instructions.is_none())
+        .count();
+    summary.push_str(&format!(
+        "{} translated, {failed} failed\n",
+        batch_results.len() - failed
+    ));
+
+    summary
+}
+
+fn run(args: &[String]) -> Result<(), Diagnostics> {
+    let cli_options = match parse_args(args)? {
+        CliCommand::Help => {
+            print!("{HELP}");
+            return Ok(());
+        }
+        CliCommand::Translate(cli_options) => cli_options,
+    };
+
+    // keep going after a failed input so that every error is reported
+    let mut diagnostics = Diagnostics::new();
+    let mut batch_results = Vec::new();
+    for input in &cli_options.inputs {
+        let result = if cli_options.batch {
+            translate_batch(input, &cli_options, &mut batch_results)
+        } else {
+            translate_input(input, &cli_options)
+        };
+        if let Err(input_diagnostics) = result {
+            diagnostics.extend(input_diagnostics);
+        }
+    }
+    if cli_options.batch && cli_options.verbosity >= Verbosity::Normal {
+        print!("{}", format_batch_summary(&batch_results));
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Err(error) = run(&args) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use vm_translator::BootstrapSetup;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn parse_cli_options(args: &[&str]) -> CliOptions {
+        match parse_args(&to_args(args)).expect("Should be valid") {
+            CliCommand::Translate(cli_options) => *cli_options,
+            CliCommand::Help => panic!("Should be a translation"),
+        }
+    }
 
     #[test]
     fn vm_file_validation_little_args() {
-        let too_little_arguments = vec!["test".to_string()];
-        let result = check_valid_vm_files(&too_little_arguments);
+        let too_little_arguments = to_args(&["test"]);
+        let result = parse_args(&too_little_arguments);
         assert!(result.is_err());
     }
 
     #[test]
     fn vm_file_validation_many_args() {
-        let too_many_arguments = vec!["test".to_string(), "test1".to_string(), "test2".to_string()];
-        let result = check_valid_vm_files(&too_many_arguments);
-        assert!(result.is_err());
+        let cli_options = parse_cli_options(&["test", "test1", "test2"]);
+        assert_eq!(
+            vec![PathBuf::from("test1"), PathBuf::from("test2")],
+            cli_options.inputs
+        );
+
+        // several inputs can not share one output file, but can all go to stdout
+        assert!(parse_args(&to_args(&["test", "-o", "out.asm", "test1", "test2"])).is_err());
+        assert!(parse_args(&to_args(&["test", "-o", "-", "test1", "test2"])).is_ok());
+
+        // "-" only means stdout, vm code can't come from stdin
+        assert!(parse_args(&to_args(&["test", "-"])).is_err());
     }
 
     #[test]
     fn vm_file_validation_bad_path() {
-        let bad_path_argument = vec!["test".to_string(), "bad_path.exe".to_string()];
-        let result = check_valid_vm_files(&bad_path_argument);
+        let cli_options = parse_cli_options(&["test", "bad_path.exe"]);
+        let result = check_valid_vm_files(
+            Path::new("bad_path.exe"),
+            Path::new("bad_path.exe"),
+            &cli_options,
+        );
         assert!(result.is_err());
     }
+
+    #[test]
+    fn vm_file_ordering() {
+        let paths = ["b/Main.vm", "a/Zeta.vm", "Sys.vm", "a/Alpha.vm"].map(PathBuf::from);
+        let sorted = ["Sys.vm", "a/Alpha.vm", "a/Zeta.vm", "b/Main.vm"].map(PathBuf::from);
+        for rotation in 0..paths.len() {
+            let mut shuffled = paths.to_vec();
+            shuffled.rotate_left(rotation);
+            assert_eq!(
+                sorted.to_vec(),
+                order_vm_files(shuffled, &[]).expect("Should be valid")
+            );
+        }
+
+        let file_order = vec!["Main".to_string(), "Zeta".to_string()];
+        assert_eq!(
+            ["b/Main.vm", "a/Zeta.vm", "Sys.vm", "a/Alpha.vm"]
+                .map(PathBuf::from)
+                .to_vec(),
+            order_vm_files(paths.to_vec(), &file_order).expect("Should be valid")
+        );
+        assert!(order_vm_files(paths.to_vec(), &["Missing".to_string()]).is_err());
+
+        let cli_options = parse_cli_options(&["test", "--order", "Main.vm, Zeta", "Foo"]);
+        assert_eq!(file_order, cli_options.file_order);
+    }
+
+    #[test]
+    fn translate_directory_twice_byte_identical() {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("08/StaticsTest");
+        let cli_options = parse_cli_options(&["test", "-o", "-", &input.display().to_string()]);
+        let first_outputs = build_outputs(&input, &input, &cli_options).expect("Should be valid");
+        let second_outputs = build_outputs(&input, &input, &cli_options).expect("Should be valid");
+
+        assert_eq!(first_outputs, second_outputs);
+        assert_eq!(include_str!("../08/StaticsTest.asm"), first_outputs[0].1);
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.vm", "Main.vm"));
+        assert!(!glob_match("*.vm", "lib/Main.vm"));
+        assert!(glob_match("?ys.vm", "Sys.vm"));
+        assert!(glob_match("lib/*.vm", "lib/Main.vm"));
+        assert!(glob_match("**/Main.vm", "Main.vm"));
+        assert!(glob_match("**/Main.vm", "a/b/Main.vm"));
+        assert!(!glob_match("**/Main.vm", "XMain.vm"));
+        assert!(!glob_match("**/Main.vm", "sub/NotMain.vm"));
+        assert!(glob_match("a/**", "a/b/Main.vm"));
+        assert!(!glob_match("Main", "Main.vm"));
+
+        let file_filter = FileFilter {
+            include: vec![String::from("Class*.vm"), String::from("Sys.vm")],
+            exclude: vec![String::from("**/Class2.vm")],
+        };
+        assert!(file_filter.matches(Path::new("Class1.vm")));
+        assert!(file_filter.matches(Path::new("sub/Sys.vm")));
+        assert!(!file_filter.matches(Path::new("Class2.vm")));
+        assert!(!file_filter.matches(Path::new("Main.vm")));
+        assert!(FileFilter::default().matches(Path::new("Main.vm")));
+
+        let cli_options = parse_cli_options(&[
+            "test",
+            "--include",
+            "*.vm",
+            "--exclude",
+            "Test*.vm",
+            "--exclude",
+            "tmp/**",
+            "-r",
+            "Foo",
+        ]);
+        assert!(cli_options.recursive);
+        assert_eq!(vec![String::from("*.vm")], cli_options.file_filter.include);
+        assert_eq!(
+            vec![String::from("Test*.vm"), String::from("tmp/**")],
+            cli_options.file_filter.exclude
+        );
+        assert!(parse_args(&to_args(&["test", "Foo", "--include"])).is_err());
+    }
+
+    #[test]
+    fn translate_directory_recursively() {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("08");
+        let cli_options = parse_cli_options(&["test", &input.display().to_string()]);
+        assert!(check_valid_vm_files(&input, &input, &cli_options).is_err());
+        assert_eq!(vec![input.clone()], get_programs(&input, &cli_options));
+
+        let cli_options = parse_cli_options(&["test", "--recursive", &input.display().to_string()]);
+        let programs = get_programs(&input, &cli_options);
+        let expected_programs = [
+            "BasicLoop",
+            "FibonacciElement",
+            "FibonacciSeries",
+            "NestedCall",
+            "SimpleFunction",
+            "StaticsTest",
+        ]
+        .map(|name| input.join(name));
+        assert_eq!(expected_programs.to_vec(), programs);
+        for program in programs {
+            let outputs = build_outputs(&program, &input, &cli_options).expect("Should be valid");
+            let expected_asm =
+                fs::read_to_string(program.with_extension("asm")).expect("Should be valid");
+            assert_eq!(vec![(program.with_extension("asm"), expected_asm)], outputs);
+        }
+
+        let statics_test = input.join("StaticsTest");
+        let cli_options = parse_cli_options(&[
+            "test",
+            "--exclude",
+            "StaticsTest/Class2.vm",
+            &input.display().to_string(),
+        ]);
+        let vm_files =
+            check_valid_vm_files(&statics_test, &input, &cli_options).expect("Should be valid");
+        assert_eq!(
+            vec![statics_test.join("Sys.vm"), statics_test.join("Class1.vm")],
+            vm_files
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recursive_skips_symlinked_directories() {
+        let input = std::env::temp_dir().join(format!("vm_translator_{}", std::process::id()));
+        let program = input.join("Program");
+        fs::create_dir_all(&program).expect("Should be valid");
+        fs::write(program.join("Main.vm"), "push constant 1").expect("Should be valid");
+        std::os::unix::fs::symlink(&input, program.join("parent")).expect("Should be valid");
+
+        let cli_options = parse_cli_options(&["test", "--recursive", &input.display().to_string()]);
+        let programs = get_programs(&input, &cli_options);
+        fs::remove_dir_all(&input).expect("Should be valid");
+        assert_eq!(vec![program], programs);
+    }
+
+    #[test]
+    fn batch_summary() {
+        let batch_results = [
+            BatchResult {
+                program: PathBuf::from("08/BasicLoop"),
+                instructions: Some(63),
+            },
+            BatchResult {
+                program: PathBuf::from("08/Broken"),
+                instructions: None,
+            },
+        ];
+        assert_eq!(
+            "program       result  instructions\n08/BasicLoop  ok                63\n08/Broken     failed             -\n1 translated, 1 failed\n",
+            format_batch_summary(&batch_results)
+        );
+
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("08");
+        let cli_options = parse_cli_options(&["test", "--batch", &input.display().to_string()]);
+        assert!(cli_options.batch);
+        let program = input.join("NestedCall");
+        assert_eq!(vec![program.clone()], get_programs(&program, &cli_options));
+        let outputs = build_outputs(&program, &input, &cli_options).expect("Should be valid");
+        assert_eq!(program.join("NestedCall.asm"), outputs[0].0);
+        assert_eq!(include_str!("../08/NestedCall.asm"), outputs[0].1);
+
+        assert!(parse_args(&to_args(&["test", "--batch", "-o", "-", "08"])).is_err());
+    }
+
+    #[test]
+    fn batch_leaf_programs() {
+        let input =
+            std::env::temp_dir().join(format!("vm_translator_batch_{}", std::process::id()));
+        let outer = input.join("Outer");
+        let inner = outer.join("Inner");
+        fs::create_dir_all(&inner).expect("Should be valid");
+        fs::write(outer.join("Main.vm"), "push constant 1").expect("Should be valid");
+        fs::write(inner.join("Main.vm"), "push constant 2").expect("Should be valid");
+
+        let input_arg = input.display().to_string();
+        let batch_programs =
+            get_programs(&input, &parse_cli_options(&["test", "--batch", &input_arg]));
+        let recursive_programs = get_programs(
+            &input,
+            &parse_cli_options(&["test", "--recursive", &input_arg]),
+        );
+        fs::remove_dir_all(&input).expect("Should be valid");
+        assert_eq!(vec![inner.clone()], batch_programs);
+        assert_eq!(vec![outer, inner], recursive_programs);
+    }
+
+    #[test]
+    fn link_check_bootstrap_entry() {
+        let vm_commands = VmCodeParser::new()
+            .parse(
+                "function Main.main 0\ncall Main.main 0\nreturn",
+                "Main.vm",
+                &get_command_symbol_table(),
+            )
+            .expect("Should be valid");
+        let vm_files = vec![VmFile::new("Main", vm_commands)];
+
+        let cli_options = parse_cli_options(&["test", "-q", "Main.vm"]);
+        assert!(cli_options.link_check);
+        assert!(check_links(&vm_files, &cli_options).is_ok());
+
+        let cli_options = parse_cli_options(&["test", "-q", "--force-bootstrap", "Main.vm"]);
+        let diagnostics = check_links(&vm_files, &cli_options).expect_err("Should be invalid");
+        assert!(matches!(
+            diagnostics.iter().next(),
+            Some(TranslateError::UndefinedFunction { name, .. }) if name == "Sys.init"
+        ));
+        let cli_options = parse_cli_options(&[
+            "test",
+            "-q",
+            "--force-bootstrap",
+            "--entry",
+            "Main.main",
+            "Main.vm",
+        ]);
+        assert!(check_links(&vm_files, &cli_options).is_ok());
+
+        assert!(!parse_cli_options(&["test", "--no-link-check", "Main.vm"]).link_check);
+    }
+
+    #[test]
+    fn call_graph_subcommand() {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("08/FibonacciElement");
+        let input_arg = input.display().to_string();
+        let cli_options = parse_cli_options(&["test", "callgraph", &input_arg]);
+        assert_eq!(Some(GraphFormat::Dot), cli_options.graph_format);
+        let outputs = build_outputs(&input, &input, &cli_options).expect("Should be valid");
+        assert_eq!(input.with_extension("dot"), outputs[0].0);
+        assert!(outputs[0]
+            .1
+            .contains("\"Main.fibonacci\" -> \"Main.fibonacci\" [label=\"2 calls, 1 arg\"];"));
+
+        let cli_options = parse_cli_options(&[
+            "test",
+            "callgraph",
+            "--format",
+            "json",
+            "-o",
+            "-",
+            &input_arg,
+        ]);
+        assert_eq!(Some(GraphFormat::Json), cli_options.graph_format);
+        let outputs = build_outputs(&input, &input, &cli_options).expect("Should be valid");
+        assert_eq!(PathBuf::from("-"), outputs[0].0);
+        assert!(outputs[0]
+            .1
+            .starts_with("{\n  \"program\": \"FibonacciElement\""));
+
+        assert_eq!(None, parse_cli_options(&["test", &input_arg]).graph_format);
+        assert!(parse_args(&to_args(&["test", "--format", "dot", "Foo.vm"])).is_err());
+        assert!(parse_args(&to_args(&[
+            "test",
+            "callgraph",
+            "--format",
+            "png",
+            "Foo.vm"
+        ]))
+        .is_err());
+        assert!(parse_args(&to_args(&["test", "callgraph", "--batch", "Foo"])).is_err());
+    }
+
+    #[test]
+    fn tree_shake_flags() {
+        let cli_options = parse_cli_options(&["test", "--tree-shake", "Foo"]);
+        assert!(cli_options.translate_options.remove_unreachable_functions);
+        assert!(cli_options.translate_options.root_functions.is_empty());
+
+        let cli_options =
+            parse_cli_options(&["test", "--root", "Main.main", "--root", "Main.other", "Foo"]);
+        assert!(cli_options.translate_options.remove_unreachable_functions);
+        assert_eq!(
+            vec![String::from("Main.main"), String::from("Main.other")],
+            cli_options.translate_options.root_functions
+        );
+
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("08/StaticsTest");
+        let input_arg = input.display().to_string();
+        let cli_options = parse_cli_options(&["test", "--tree-shake", "--emit", "ir", &input_arg]);
+        let outputs = build_outputs(&input, &input, &cli_options).expect("Should be valid");
+        assert!(outputs[0].1.contains("function Class2.get 0"));
+
+        assert!(parse_args(&to_args(&["test", "--root"])).is_err());
+        assert!(parse_args(&to_args(&["test", "--stream", "--tree-shake", "Foo"])).is_err());
+    }
+
+    #[test]
+    fn emit_format_flag() {
+        let cli_options = parse_cli_options(&["test", "--emit", "both", "Foo.vm"]);
+        assert_eq!(vec![PathBuf::from("Foo.vm")], cli_options.inputs);
+        assert_eq!(EmitFormat::Both, cli_options.emit_format);
+
+        let cli_options = parse_cli_options(&["test", "Foo.vm"]);
+        assert_eq!(EmitFormat::Asm, cli_options.emit_format);
+
+        for (format, emit_format) in [("ir", EmitFormat::Ir), ("json", EmitFormat::Json)] {
+            let cli_options = parse_cli_options(&["test", "--emit", format, "Foo.vm"]);
+            assert_eq!(emit_format, cli_options.emit_format);
+        }
+
+        assert!(parse_args(&to_args(&["test", "--emit", "exe"])).is_err());
+    }
+
+    #[test]
+    fn bootstrap_setup_flags() {
+        let cli_options = parse_cli_options(&[
+            "test",
+            "--force-bootstrap",
+            "--entry",
+            "SimpleFunction.test",
+            "--entry-args",
+            "2",
+            "--sp",
+            "312",
+            "--lcl",
+            "305",
+            "--arg",
+            "300",
+            "--this",
+            "3010",
+            "--that",
+            "4010",
+            "SimpleFunction.vm",
+        ]);
+        assert_eq!(
+            BootstrapSetup {
+                entry_function: String::from("SimpleFunction.test"),
+                entry_nargs: 2,
+                sp: 312,
+                lcl: Some(305),
+                arg: Some(300),
+                this: Some(3010),
+                that: Some(4010),
+            },
+            cli_options.translate_options.bootstrap_setup
+        );
+    }
+
+    #[test]
+    fn translate_option_flags() {
+        let cli_options = parse_cli_options(&[
+            "test",
+            "--compact",
+            "-O",
+            "--no-bootstrap",
+            "--quiet",
+            "-o",
+            "-",
+            "Foo.vm",
+        ]);
+        assert_eq!(vec![PathBuf::from("Foo.vm")], cli_options.inputs);
+        assert_eq!(Some(PathBuf::from("-")), cli_options.output);
+        assert!(cli_options.translate_options.compact);
+        assert_eq!(1, cli_options.translate_options.optimization_level);
+        assert_eq!(Bootstrap::Suppress, cli_options.translate_options.bootstrap);
+        assert_eq!(Verbosity::Quiet, cli_options.verbosity);
+
+        let cli_options = parse_cli_options(&["test", "-O2", "--force-bootstrap", "-v", "Foo.vm"]);
+        assert_eq!(2, cli_options.translate_options.optimization_level);
+        assert_eq!(Bootstrap::Force, cli_options.translate_options.bootstrap);
+        assert_eq!(Verbosity::Verbose, cli_options.verbosity);
+
+        let cli_options = parse_cli_options(&["test", "Foo.vm"]);
+        assert_eq!(TranslateOptions::default(), cli_options.translate_options);
+        assert_eq!(Verbosity::Normal, cli_options.verbosity);
+
+        assert!(matches!(
+            parse_args(&to_args(&["test", "--help"])),
+            Ok(CliCommand::Help)
+        ));
+        assert!(parse_args(&to_args(&["test", "--frobnicate", "Foo.vm"])).is_err());
+        assert!(parse_args(&to_args(&["test", "--sp", "-1", "Foo.vm"])).is_err());
+        assert!(parse_args(&to_args(&["test", "--lcl", "40000", "Foo.vm"])).is_err());
+        assert!(parse_args(&to_args(&["test", "Foo.vm", "-o"])).is_err());
+
+        assert!(parse_cli_options(&["test", "--stream", "Foo.vm"]).stream);
+        assert!(parse_args(&to_args(&["test", "--stream", "-O", "Foo.vm"])).is_err());
+        assert!(parse_args(&to_args(&["test", "--stream", "--emit", "hack", "Foo.vm"])).is_err());
+    }
 }
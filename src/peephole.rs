@@ -0,0 +1,251 @@
+use std::fmt;
+
+// the end of every push followed by the start of a pop or a binary arithmetic command
+const PUSH_POP: [&str; 8] = ["@SP", "A=M", "M=D", "@SP", "M=M+1", "@SP", "AM=M-1", "D=M"];
+// what is left of it, D still holds the pushed value and A points at the top of the stack
+const PUSH_POP_FUSED: [&str; 2] = ["@SP", "A=M"];
+
+// one line of hack assembly, structured so the passes can look at its parts
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum AsmInstruction {
+    Address(String),
+    Compute {
+        dest: String,
+        comp: String,
+        jump: String,
+    },
+    Label(String),
+}
+
+impl AsmInstruction {
+    fn parse(instruction: &str) -> AsmInstruction {
+        if let Some(symbol) = instruction.strip_prefix('@') {
+            return AsmInstruction::Address(symbol.to_string());
+        }
+        if let Some(label_name) = instruction
+            .strip_prefix('(')
+            .and_then(|label| label.strip_suffix(')'))
+        {
+            return AsmInstruction::Label(label_name.to_string());
+        }
+
+        let (dest, comp_and_jump) = instruction.split_once('=').unwrap_or(("", instruction));
+        let (comp, jump) = comp_and_jump.split_once(';').unwrap_or((comp_and_jump, ""));
+        AsmInstruction::Compute {
+            dest: dest.to_string(),
+            comp: comp.to_string(),
+            jump: jump.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AsmInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmInstruction::Address(symbol) => write!(f, "@{symbol}"),
+            AsmInstruction::Label(label_name) => write!(f, "({label_name})"),
+            AsmInstruction::Compute { dest, comp, jump } => {
+                if !dest.is_empty() {
+                    write!(f, "{dest}=")?;
+                }
+                write!(f, "{comp}")?;
+                if !jump.is_empty() {
+                    write!(f, ";{jump}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// rewrites generated assembly into an equivalent shorter program,
+// the passes are repeated until none of them finds anything to remove
+pub fn optimize_asm(asm_code: &str) -> String {
+    let mut instructions: Vec<AsmInstruction> = asm_code
+        .lines()
+        .map(|line| match line.find("//") {
+            Some(comment_start) => line[..comment_start].trim(),
+            None => line.trim(),
+        })
+        .filter(|line| !line.is_empty())
+        .map(AsmInstruction::parse)
+        .collect();
+
+    loop {
+        let fused = fuse_push_pop(&mut instructions);
+        let redundant_removed = remove_redundant_addresses(&mut instructions);
+        let dead_removed = remove_dead_writes(&mut instructions);
+        if !(fused || redundant_removed || dead_removed) {
+            break;
+        }
+    }
+
+    let mut optimized_asm = instructions
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect::<Vec<String>>()
+        .join("\n");
+    if asm_code.ends_with('\n') {
+        optimized_asm.push('\n');
+    }
+
+    optimized_asm
+}
+
+// a value pushed and popped straight away never has to go through the stack
+fn fuse_push_pop(instructions: &mut Vec<AsmInstruction>) -> bool {
+    let mut fused = false;
+    let mut index = 0;
+    while index + PUSH_POP.len() <= instructions.len() {
+        let is_push_pop = instructions[index..index + PUSH_POP.len()]
+            .iter()
+            .zip(PUSH_POP)
+            .all(|(instruction, pattern)| instruction.to_string() == pattern);
+        if is_push_pop {
+            instructions.splice(
+                index..index + PUSH_POP.len(),
+                PUSH_POP_FUSED.map(AsmInstruction::parse),
+            );
+            fused = true;
+        }
+        index += 1;
+    }
+
+    fused
+}
+
+// drops @X when A already holds X, labels are jump targets so A is unknown after them
+fn remove_redundant_addresses(instructions: &mut Vec<AsmInstruction>) -> bool {
+    let mut known_address: Option<String> = None;
+    let instruction_count = instructions.len();
+    instructions.retain(|instruction| match instruction {
+        AsmInstruction::Address(symbol) => {
+            if known_address.as_ref() == Some(symbol) {
+                return false;
+            }
+            known_address = Some(symbol.clone());
+            true
+        }
+        AsmInstruction::Compute { dest, .. } => {
+            if dest.contains('A') {
+                known_address = None;
+            }
+            true
+        }
+        AsmInstruction::Label(_) => {
+            known_address = None;
+            true
+        }
+    });
+
+    instructions.len() != instruction_count
+}
+
+// drops writes to A and D that are overwritten before anything reads them
+fn remove_dead_writes(instructions: &mut Vec<AsmInstruction>) -> bool {
+    let dead: Vec<bool> = (0..instructions.len())
+        .map(|index| {
+            let next_instruction = instructions.get(index + 1);
+            match &instructions[index] {
+                AsmInstruction::Address(_) => {
+                    matches!(next_instruction, Some(AsmInstruction::Address(_)))
+                }
+                AsmInstruction::Compute { dest, jump, .. } if dest == "A" && jump.is_empty() => {
+                    matches!(next_instruction, Some(AsmInstruction::Address(_)))
+                }
+                AsmInstruction::Compute { dest, jump, .. } if dest == "D" && jump.is_empty() => {
+                    is_d_overwritten(&instructions[index + 1..])
+                }
+                _ => false,
+            }
+        })
+        .collect();
+
+    let mut dead_iter = dead.iter();
+    instructions.retain(|_| !dead_iter.next().is_some_and(|&is_dead| is_dead));
+    dead.contains(&true)
+}
+
+// D is dead when it is written again before being read, a label or jump could lead
+// anywhere so D is treated as read there
+fn is_d_overwritten(instructions: &[AsmInstruction]) -> bool {
+    for instruction in instructions {
+        match instruction {
+            AsmInstruction::Address(_) => {}
+            AsmInstruction::Label(_) => return false,
+            AsmInstruction::Compute { dest, comp, jump } => {
+                if comp.contains('D') || !jump.is_empty() {
+                    return false;
+                }
+                if dest.contains('D') {
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HackCpu;
+
+    fn run_asm(asm_code: &str, initial_ram: &[(u16, i16)], steps: usize) -> HackCpu {
+        let mut hack_cpu = HackCpu::from_asm(asm_code, "peephole.asm").expect("Should be valid");
+        for &(address, value) in initial_ram {
+            hack_cpu.set_ram(address, value);
+        }
+        let mut step = 0;
+        while step < steps && !hack_cpu.is_idle() {
+            hack_cpu.step();
+            step += 1;
+        }
+        hack_cpu
+    }
+
+    #[test]
+    fn optimize_push_pop_pairs() {
+        // push constant 7, pop static 0, push constant 8, push constant 9, add
+        let asm_code = "@7\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nAM=M-1\nD=M\n@Foo.0\nM=D\n@8\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@9\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nAM=M-1\nD=M\nA=A-1\nM=D+M\n";
+
+        assert_eq!(
+            "@7\nD=A\n@Foo.0\nM=D\n@8\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@9\nD=A\n@SP\nA=M\nA=A-1\nM=D+M\n",
+            optimize_asm(asm_code)
+        );
+    }
+
+    #[test]
+    fn optimize_keeps_live_values() {
+        // D is read by the jump target and A is unknown after a label
+        let asm_code = "@5\nD=A\n@target\n0;JMP\n(target)\n@SP\nM=D\n@SP\nD=M\n@SP\nM=D+1";
+        assert_eq!(
+            "@5\nD=A\n@target\n0;JMP\n(target)\n@SP\nM=D\nD=M\nM=D+1",
+            optimize_asm(asm_code)
+        );
+    }
+
+    #[test]
+    fn optimize_translated_fixtures() {
+        let fixtures = [
+            include_str!("../SimpleAdd.asm"),
+            include_str!("../StackTest.asm"),
+            include_str!("../BasicTest.asm"),
+            include_str!("../PointerTest.asm"),
+            include_str!("../StaticTest.asm"),
+        ];
+        let initial_ram = [(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+        for asm_code in fixtures {
+            let optimized_asm = optimize_asm(asm_code);
+            assert!(optimized_asm.lines().count() < asm_code.lines().count());
+
+            let hack_cpu = run_asm(asm_code, &initial_ram, 10_000);
+            let optimized_hack_cpu = run_asm(&optimized_asm, &initial_ram, 10_000);
+            let sp = hack_cpu.ram(0) as u16;
+            for address in (0..13).chain(16..sp).chain(3000..3020) {
+                assert_eq!(hack_cpu.ram(address), optimized_hack_cpu.ram(address));
+            }
+        }
+    }
+}
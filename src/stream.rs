@@ -0,0 +1,287 @@
+use crate::{
+    get_command_symbol_table, write_bootstrap, Diagnostics, LabelAllocator, TranslateError,
+    TranslateOptions, VMCommandType, VmCodeParser, VmCodeWriter, WriterState, END_LOOP,
+};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+// translates a program line by line from readers into a writer, so memory stays the same
+// however large the vm files are, only the current line and the errors found so far are kept
+// the output is the same as translate_program_with_options, except that the whole
+// program passes of -O, -O2 and removing unreachable functions can not run on a stream
+// and are rejected
+pub struct StreamTranslator<W: Write> {
+    writer: W,
+    program_name: String,
+    vm_code_writer: VmCodeWriter,
+    command_table: HashMap<VMCommandType, Vec<&'static str>>,
+    label_allocator: LabelAllocator,
+    bootstrap_code_exists: bool,
+    diagnostics: Diagnostics,
+}
+
+impl<W: Write> StreamTranslator<W> {
+    // writes everything that comes before the first file, the file names decide
+    // whether there is bootstrap code just like for translate_program_with_options
+    pub fn new(
+        writer: W,
+        program_name: &str,
+        file_names: &[&str],
+        options: &TranslateOptions,
+    ) -> Result<StreamTranslator<W>, Diagnostics> {
+        if options.optimization_level > 0 || options.remove_unreachable_functions {
+            return Err(TranslateError::InvalidArguments(String::from(
+                "Streaming translation does not support optimization levels above 0 or removing unreachable functions.",
+            ))
+            .into());
+        }
+
+        let vm_code_writer = VmCodeWriter::with_options(Vec::new(), options.clone());
+        let mut stream_translator = StreamTranslator {
+            writer,
+            program_name: program_name.to_string(),
+            vm_code_writer,
+            command_table: get_command_symbol_table(),
+            label_allocator: LabelAllocator::new(),
+            bootstrap_code_exists: options.emits_bootstrap_for(file_names.iter().copied()),
+            diagnostics: Diagnostics::new(),
+        };
+        if options.compact {
+            let compact_routines = stream_translator.vm_code_writer.write_compact_routines();
+            stream_translator.write(&compact_routines)?;
+        }
+        if stream_translator.bootstrap_code_exists {
+            let bootstrap_code = write_bootstrap(
+                program_name,
+                options,
+                &mut stream_translator.label_allocator,
+            )?;
+            stream_translator.write(&bootstrap_code)?;
+        }
+
+        Ok(stream_translator)
+    }
+
+    // translates one vm file, errors in it are kept for finish so every file gets checked
+    // io errors stop the translation straight away
+    // file_name is the file stem static variables are namespaced by, source_name the
+    // path errors point at
+    pub fn translate_file<R: BufRead>(
+        &mut self,
+        mut reader: R,
+        file_name: &str,
+        source_name: &str,
+    ) -> Result<(), Diagnostics> {
+        let vm_code_parser = VmCodeParser::new();
+        let mut writer_state = WriterState::default();
+        let mut current_line = String::new();
+        let mut line_number = 0;
+        loop {
+            current_line.clear();
+            let bytes_read =
+                reader
+                    .read_line(&mut current_line)
+                    .map_err(|source| TranslateError::Io {
+                        path: PathBuf::from(source_name),
+                        source,
+                    })?;
+            if bytes_read == 0 {
+                break;
+            }
+            line_number += 1;
+
+            let line = current_line.trim_end_matches('\n');
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            match vm_code_parser.parse_line(line, line_number, source_name, &self.command_table) {
+                Ok(Some(parsed_command)) => {
+                    let translated_command = self.vm_code_writer.write_command(
+                        &parsed_command,
+                        file_name,
+                        &mut writer_state,
+                        &mut self.label_allocator,
+                    );
+                    match translated_command {
+                        Some(translated_command) => {
+                            self.write(&translated_command)?;
+                            self.write("\n")?;
+                        }
+                        None => {
+                            let error = self.vm_code_writer.translation_error(&parsed_command);
+                            self.diagnostics.push(error);
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => self.diagnostics.push(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    // ends the program and hands back the writer, or every error found in any file
+    pub fn finish(mut self) -> Result<W, Diagnostics> {
+        if !self.bootstrap_code_exists {
+            self.write(END_LOOP)?;
+        }
+        self.writer.flush().map_err(|source| TranslateError::Io {
+            path: PathBuf::from(&self.program_name),
+            source,
+        })?;
+
+        if self.diagnostics.is_empty() {
+            Ok(self.writer)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn write(&mut self, asm_code: &str) -> Result<(), TranslateError> {
+        self.writer
+            .write_all(asm_code.as_bytes())
+            .map_err(|source| TranslateError::Io {
+                path: PathBuf::from(&self.program_name),
+                source,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_vm_fixture, read_vm_fixture, translate_program_with_options};
+    use std::io;
+
+    // a vm file of the same command repeated, produced as it is read
+    struct RepeatedCommand {
+        command: &'static [u8],
+        remaining: usize,
+        position: usize,
+    }
+
+    impl io::Read for RepeatedCommand {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut read = 0;
+            while read < buf.len() && self.remaining > 0 {
+                buf[read] = self.command[self.position];
+                read += 1;
+                self.position += 1;
+                if self.position == self.command.len() {
+                    self.position = 0;
+                    self.remaining -= 1;
+                }
+            }
+            Ok(read)
+        }
+    }
+
+    // only counts what is written
+    #[derive(Default)]
+    struct CountingSink {
+        bytes: usize,
+        lines: usize,
+    }
+
+    impl Write for CountingSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes += buf.len();
+            self.lines += buf.iter().filter(|&&byte| byte == b'\n').count();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_matches_translate_program() {
+        for compact in [false, true] {
+            let options = TranslateOptions {
+                compact,
+                ..TranslateOptions::default()
+            };
+            for fixture in ["FibonacciElement", "NestedCall", "StaticsTest"] {
+                let vm_sources = read_vm_fixture(fixture);
+                let names: Vec<&str> = vm_sources.iter().map(|(name, _)| name.as_str()).collect();
+
+                let mut stream_translator =
+                    StreamTranslator::new(Vec::new(), fixture, &names, &options)
+                        .expect("Should be valid");
+                for (name, vm_code) in &vm_sources {
+                    stream_translator
+                        .translate_file(vm_code.as_bytes(), name, name)
+                        .expect("Should be valid");
+                }
+                let streamed_asm = stream_translator.finish().expect("Should be valid");
+
+                let vm_files = parse_vm_fixture(&vm_sources);
+                let asm_code = translate_program_with_options(vm_files, fixture, &options)
+                    .expect("Should be valid");
+                assert_eq!(asm_code, String::from_utf8(streamed_asm).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn stream_large_input() {
+        // 200000 lines in, push constant 1 and pop temp 0 take 19 instructions together
+        let reader = io::BufReader::new(RepeatedCommand {
+            command: b"push constant 1\r\n\tpop temp 0 // discard\n",
+            remaining: 100_000,
+            position: 0,
+        });
+        let mut stream_translator = StreamTranslator::new(
+            CountingSink::default(),
+            "Main",
+            &["Main"],
+            &TranslateOptions::default(),
+        )
+        .expect("Should be valid");
+        stream_translator
+            .translate_file(reader, "Main", "Main.vm")
+            .expect("Should be valid");
+        let counting_sink = stream_translator.finish().expect("Should be valid");
+
+        assert_eq!(100_000 * 19 + 2, counting_sink.lines);
+    }
+
+    #[test]
+    fn stream_collects_errors_of_every_file() {
+        let mut stream_translator = StreamTranslator::new(
+            Vec::new(),
+            "Main",
+            &["Main", "Other"],
+            &TranslateOptions::default(),
+        )
+        .expect("Should be valid");
+        stream_translator
+            .translate_file(
+                "push constant 1\npish constant 2".as_bytes(),
+                "Main",
+                "Main.vm",
+            )
+            .expect("Should be valid");
+        stream_translator
+            .translate_file("pop constant 3".as_bytes(), "Other", "Other.vm")
+            .expect("Should be valid");
+        let diagnostics = stream_translator.finish().unwrap_err();
+
+        assert_eq!(2, diagnostics.len());
+        let spans: Vec<String> = diagnostics
+            .iter()
+            .filter_map(|error| error.span())
+            .map(|span| span.to_string())
+            .collect();
+        assert_eq!(vec!["Main.vm:2:1", "Other.vm:1:1"], spans);
+
+        let options = TranslateOptions {
+            optimization_level: 1,
+            ..TranslateOptions::default()
+        };
+        assert!(StreamTranslator::new(Vec::new(), "Main", &["Main"], &options).is_err());
+    }
+}
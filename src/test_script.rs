@@ -0,0 +1,524 @@
+use crate::{Diagnostics, HackCpu, Span, TranslateError};
+use std::fmt;
+
+// something a nand2tetris test script can drive, e.g. the cpu emulator
+pub trait ScriptTarget {
+    // runs a step command such as ticktock, false if the target doesn't support it
+    fn step(&mut self, step_command: &str) -> bool;
+    fn variable(&self, name: &str) -> Option<i16>;
+    fn set_variable(&mut self, name: &str, value: i16) -> bool;
+    // false if the program a load command names isn't the one the target holds,
+    // a load without a file name stands for the whole directory
+    fn load(&mut self, file_name: Option<&str>) -> bool;
+}
+
+impl ScriptTarget for HackCpu {
+    fn step(&mut self, step_command: &str) -> bool {
+        match step_command {
+            "ticktock" => {
+                HackCpu::step(self);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<i16> {
+        match name {
+            "A" => Some(self.a()),
+            "D" => Some(self.d()),
+            "PC" => Some(self.pc() as i16),
+            _ => ram_address(name).map(|address| self.ram(address)),
+        }
+    }
+
+    fn set_variable(&mut self, name: &str, value: i16) -> bool {
+        match ram_address(name) {
+            Some(address) => {
+                self.set_ram(address, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // the program is assembled by the caller, so any assembly or binary file is taken as it
+    fn load(&mut self, file_name: Option<&str>) -> bool {
+        file_name
+            .is_some_and(|file_name| file_name.ends_with(".asm") || file_name.ends_with(".hack"))
+    }
+}
+
+// RAM[n] to n
+pub(crate) fn ram_address(name: &str) -> Option<u16> {
+    name.strip_prefix("RAM[")
+        .and_then(|name| name.strip_suffix(']'))
+        .and_then(|address| address.parse::<u16>().ok())
+}
+
+// a column of output-list, e.g. RAM[0]%D1.6.1
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct OutputColumn {
+    variable: String,
+    format: char,
+    pad_left: usize,
+    width: usize,
+    pad_right: usize,
+}
+
+impl OutputColumn {
+    fn header(&self) -> String {
+        let total_width = self.pad_left + self.width + self.pad_right;
+        let name: String = self.variable.chars().take(total_width).collect();
+        let left_space = (total_width - name.len()) / 2;
+        let right_space = total_width - name.len() - left_space;
+        format!(
+            "{}{name}{}",
+            " ".repeat(left_space),
+            " ".repeat(right_space)
+        )
+    }
+
+    fn value(&self, value: i16) -> String {
+        let formatted_value = match self.format {
+            'X' => format!("{:04X}", value as u16),
+            'B' => format!("{:016b}", value as u16),
+            _ => value.to_string(),
+        };
+        let skip = formatted_value.len().saturating_sub(self.width);
+        format!(
+            "{}{:>width$}{}",
+            " ".repeat(self.pad_left),
+            &formatted_value[skip..],
+            " ".repeat(self.pad_right),
+            width = self.width
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ScriptCommand {
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<OutputColumn>),
+    Output,
+    Set {
+        variable: String,
+        value: i16,
+    },
+    Repeat {
+        count: usize,
+        body: Vec<(ScriptCommand, Span)>,
+    },
+    Step(String),
+}
+
+// output lines of a test script run, in the same format as the *.cmp files
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ScriptOutput {
+    pub compare_to: Option<String>,
+    pub output_file: Option<String>,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "comparison failure at line {}\n  expected: {}\n    actual: {}",
+            self.line, self.expected, self.actual
+        )
+    }
+}
+
+impl ScriptOutput {
+    // compares line by line, a * in the compare file matches any character
+    pub fn compare(&self, cmp_code: &str) -> Vec<Mismatch> {
+        let expected_lines: Vec<&str> = cmp_code.lines().map(str::trim_end).collect();
+        let line_count = expected_lines.len().max(self.lines.len());
+        (0..line_count)
+            .filter_map(|line_index| {
+                let expected = expected_lines.get(line_index).copied().unwrap_or_default();
+                let actual = self.lines.get(line_index).map_or("", String::as_str);
+                let matches = expected.len() == actual.len()
+                    && expected
+                        .chars()
+                        .zip(actual.chars())
+                        .all(|(expected, actual)| expected == '*' || expected == actual);
+                (!matches).then(|| Mismatch {
+                    line: line_index + 1,
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    span: Span,
+}
+
+// splits a script into words and the , ; { } separators, skipping comments
+fn tokenize(script: &str, file_name: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut in_block_comment = false;
+    for (line_index, current_line) in script.lines().enumerate() {
+        let chars: Vec<char> = current_line.chars().collect();
+        let mut column = 0;
+        while column < chars.len() {
+            if in_block_comment {
+                if chars[column] == '*' && chars.get(column + 1) == Some(&'/') {
+                    in_block_comment = false;
+                    column += 1;
+                }
+                column += 1;
+                continue;
+            }
+
+            let current_char = chars[column];
+            if current_char == '/' && chars.get(column + 1) == Some(&'/') {
+                break;
+            } else if current_char == '/' && chars.get(column + 1) == Some(&'*') {
+                in_block_comment = true;
+                column += 2;
+                continue;
+            } else if current_char.is_whitespace() {
+                column += 1;
+                continue;
+            }
+
+            let start = column;
+            if ",;{}".contains(current_char) {
+                column += 1;
+            } else {
+                while column < chars.len()
+                    && !chars[column].is_whitespace()
+                    && !",;{}".contains(chars[column])
+                {
+                    column += 1;
+                }
+            }
+            tokens.push(Token {
+                text: chars[start..column].iter().collect(),
+                span: Span {
+                    file: file_name.to_string(),
+                    line: line_index + 1,
+                    column: start + 1,
+                    length: column - start,
+                    source_line: current_line.to_string(),
+                },
+            });
+        }
+    }
+
+    tokens
+}
+
+struct ScriptParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl ScriptParser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    // arguments up to the , or ; that ends a command
+    fn arguments(&mut self) -> Vec<Token> {
+        let mut arguments = Vec::new();
+        while let Some(text) = self.peek() {
+            if ",;{}".contains(text) {
+                if text == "," || text == ";" {
+                    self.position += 1;
+                }
+                break;
+            }
+            arguments.push(self.tokens[self.position].clone());
+            self.position += 1;
+        }
+
+        arguments
+    }
+
+    fn parse_commands(
+        &mut self,
+        diagnostics: &mut Diagnostics,
+        in_repeat: bool,
+    ) -> Vec<(ScriptCommand, Span)> {
+        let mut commands = Vec::new();
+        while let Some(token) = self.next() {
+            let command = token.text.clone();
+            let span = token.span.clone();
+            if command == "}" && in_repeat {
+                return commands;
+            }
+
+            let arguments = if command == "repeat" {
+                Vec::new()
+            } else {
+                self.arguments()
+            };
+            let argument_texts: Vec<&str> = arguments.iter().map(|a| a.text.as_str()).collect();
+            let script_command = match (command.as_str(), argument_texts.as_slice()) {
+                ("load", []) => Some(ScriptCommand::Load(None)),
+                ("load", [file]) => Some(ScriptCommand::Load(Some(file.to_string()))),
+                ("output-file", [file]) => Some(ScriptCommand::OutputFile(file.to_string())),
+                ("compare-to", [file]) => Some(ScriptCommand::CompareTo(file.to_string())),
+                ("output", []) => Some(ScriptCommand::Output),
+                ("set", [variable, value]) => {
+                    value.parse::<i16>().ok().map(|value| ScriptCommand::Set {
+                        variable: variable.to_string(),
+                        value,
+                    })
+                }
+                ("output-list", columns) => columns
+                    .iter()
+                    .map(|column| parse_output_column(column))
+                    .collect::<Option<Vec<OutputColumn>>>()
+                    .map(ScriptCommand::OutputList),
+                ("repeat", _) => {
+                    let count = self
+                        .next()
+                        .and_then(|count| count.text.parse::<usize>().ok());
+                    let has_body = self.next().is_some_and(|brace| brace.text == "{");
+                    match (count, has_body) {
+                        (Some(count), true) => Some(ScriptCommand::Repeat {
+                            count,
+                            body: self.parse_commands(diagnostics, true),
+                        }),
+                        _ => None,
+                    }
+                }
+                ("ticktock" | "tick" | "tock" | "vmstep" | "eval", []) => {
+                    Some(ScriptCommand::Step(command.clone()))
+                }
+                _ => None,
+            };
+
+            match script_command {
+                Some(script_command) => commands.push((script_command, span)),
+                None => diagnostics.push(TranslateError::InvalidScriptCommand { command, span }),
+            }
+        }
+
+        commands
+    }
+}
+
+// %D1.6.1 is decimal with 1 space of left padding, a width of 6 and 1 space of right padding
+fn parse_output_column(column: &str) -> Option<OutputColumn> {
+    let (variable, format) = column.split_once('%')?;
+    let mut format_chars = format.chars();
+    let format_type = format_chars.next().filter(|c| "DXBS".contains(*c))?;
+    let sizes: Vec<usize> = format_chars
+        .as_str()
+        .split('.')
+        .map(|size| size.parse::<usize>().ok())
+        .collect::<Option<Vec<usize>>>()?;
+    match sizes.as_slice() {
+        [pad_left, width, pad_right] => Some(OutputColumn {
+            variable: variable.to_string(),
+            format: format_type,
+            pad_left: *pad_left,
+            width: *width,
+            pad_right: *pad_right,
+        }),
+        _ => None,
+    }
+}
+
+// runs a *.tst script against a target that already has the program loaded,
+// load only checks the target holds the named program and output-file is left to the caller
+pub fn run_test_script<T: ScriptTarget>(
+    script: &str,
+    file_name: &str,
+    target: &mut T,
+) -> Result<ScriptOutput, Diagnostics> {
+    let mut script_parser = ScriptParser {
+        tokens: tokenize(script, file_name),
+        position: 0,
+    };
+    let mut diagnostics = Diagnostics::new();
+    let commands = script_parser.parse_commands(&mut diagnostics, false);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let mut script_output = ScriptOutput::default();
+    let mut output_list: Vec<OutputColumn> = Vec::new();
+    run_commands(
+        &commands,
+        target,
+        &mut output_list,
+        &mut script_output,
+        &mut diagnostics,
+    );
+
+    if diagnostics.is_empty() {
+        Ok(script_output)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn run_commands<T: ScriptTarget>(
+    commands: &[(ScriptCommand, Span)],
+    target: &mut T,
+    output_list: &mut Vec<OutputColumn>,
+    script_output: &mut ScriptOutput,
+    diagnostics: &mut Diagnostics,
+) {
+    for (command, span) in commands {
+        let invalid_variable = |variable: &str| TranslateError::InvalidScriptCommand {
+            command: variable.to_string(),
+            span: span.clone(),
+        };
+        match command {
+            ScriptCommand::Load(file_name) => {
+                if !target.load(file_name.as_deref()) {
+                    return diagnostics.push(TranslateError::InvalidScriptCommand {
+                        command: format!("load {}", file_name.as_deref().unwrap_or_default()),
+                        span: span.clone(),
+                    });
+                }
+            }
+            ScriptCommand::OutputFile(file) => script_output.output_file = Some(file.clone()),
+            ScriptCommand::CompareTo(file) => script_output.compare_to = Some(file.clone()),
+            ScriptCommand::OutputList(columns) => {
+                *output_list = columns.clone();
+                let headers: Vec<String> = columns.iter().map(OutputColumn::header).collect();
+                script_output.lines.push(format!("|{}|", headers.join("|")));
+            }
+            ScriptCommand::Output => {
+                let mut values: Vec<String> = Vec::new();
+                for column in output_list.iter() {
+                    match target.variable(&column.variable) {
+                        Some(value) => values.push(column.value(value)),
+                        None => return diagnostics.push(invalid_variable(&column.variable)),
+                    }
+                }
+                script_output.lines.push(format!("|{}|", values.join("|")));
+            }
+            ScriptCommand::Set { variable, value } => {
+                if !target.set_variable(variable, *value) {
+                    return diagnostics.push(invalid_variable(variable));
+                }
+            }
+            ScriptCommand::Repeat { count, body } => {
+                for _repeat in 0..*count {
+                    run_commands(body, target, output_list, script_output, diagnostics);
+                    if !diagnostics.is_empty() {
+                        return;
+                    }
+                }
+            }
+            ScriptCommand::Step(step_command) => {
+                if !target.step(step_command) {
+                    return diagnostics.push(invalid_variable(step_command));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_vm_fixture, read_vm_fixture, translate_program};
+    use std::{fs, path::Path};
+
+    // translates every vm file of a fixture directory, Sys first like the cli does
+    fn load_fixture_program(fixture: &str) -> HackCpu {
+        let vm_files = parse_vm_fixture(&read_vm_fixture(fixture));
+        let asm_code = translate_program(vm_files, fixture).expect("Should be valid");
+        HackCpu::from_asm(&asm_code, fixture).expect("Should be valid")
+    }
+
+    #[test]
+    fn run_fixture_test_scripts() {
+        let fixtures = [
+            "BasicLoop",
+            "FibonacciSeries",
+            "SimpleFunction",
+            "NestedCall",
+            "FibonacciElement",
+            "StaticsTest",
+        ];
+        for fixture in fixtures {
+            let directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("08")
+                .join(fixture);
+            let script_path = directory.join(format!("{fixture}.tst"));
+            let script = fs::read_to_string(&script_path).expect("Should be valid");
+            let mut hack_cpu = load_fixture_program(fixture);
+
+            let script_output = run_test_script(&script, fixture, &mut hack_cpu)
+                .unwrap_or_else(|diagnostics| panic!("{diagnostics}"));
+            let compare_to = script_output.compare_to.clone().expect("Should be set");
+            let cmp_code = fs::read_to_string(directory.join(compare_to)).expect("Should be valid");
+            let mismatches = script_output.compare(&cmp_code);
+            assert!(mismatches.is_empty(), "{fixture}: {mismatches:?}");
+        }
+    }
+
+    #[test]
+    fn format_output_columns() {
+        let column = parse_output_column("RAM[3000]%D1.6.2").expect("Should be valid");
+
+        assert_eq!("RAM[3000]", column.header());
+        assert_eq!("      0  ", column.value(0));
+        assert_eq!("     -2  ", column.value(-2));
+        assert_eq!(
+            " RAM[0] ",
+            parse_output_column("RAM[0]%D1.6.1").unwrap().header()
+        );
+        assert!(parse_output_column("RAM[0]%Q1.6.1").is_none());
+    }
+
+    #[test]
+    fn report_mismatches() {
+        let script = "/* block\ncomment */ set RAM[0] 7, // SP\nrepeat 2 { ticktock; }\noutput-list RAM[0]%D1.6.1 RAM[1]%D1.6.1;\noutput;";
+        let mut hack_cpu = HackCpu::new();
+        let script_output =
+            run_test_script(script, "test.tst", &mut hack_cpu).expect("Should be valid");
+
+        assert_eq!(
+            vec!["| RAM[0] | RAM[1] |", "|      7 |      0 |"],
+            script_output.lines
+        );
+        let mismatches = script_output.compare("| RAM[0] | RAM[1] |\n|      8 |      * |");
+        assert_eq!(1, mismatches.len());
+        assert_eq!(2, mismatches[0].line);
+    }
+
+    #[test]
+    fn reject_invalid_scripts() {
+        let mut hack_cpu = HackCpu::new();
+
+        assert!(run_test_script("fly 6000;", "bad.tst", &mut hack_cpu).is_err());
+        assert!(run_test_script("repeat { ticktock; }", "bad.tst", &mut hack_cpu).is_err());
+        assert!(run_test_script("vmstep;", "bad.tst", &mut hack_cpu).is_err());
+        assert!(run_test_script("load Main.vm;", "bad.tst", &mut hack_cpu).is_err());
+        assert!(run_test_script("load a.asm b.asm;", "bad.tst", &mut hack_cpu).is_err());
+    }
+}
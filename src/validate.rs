@@ -0,0 +1,142 @@
+use crate::{Diagnostics, Segment, Span, TranslateError, VmCommand, VmFile};
+
+// largest function or call count, the same as the largest constant
+const MAX_COUNT: i32 = i16::MAX as i32;
+
+// the index of a push or pop as written, checked against the segment it is used with
+pub(crate) fn check_index(
+    command: &str,
+    segment: Segment,
+    index: i32,
+    span: &Span,
+) -> Result<u16, TranslateError> {
+    if command == "pop" && segment == Segment::Constant {
+        return Err(TranslateError::BadSegment {
+            command: command.to_string(),
+            segment: segment.name().to_string(),
+            span: span.clone(),
+        });
+    }
+
+    u16::try_from(index)
+        .ok()
+        .filter(|&index| index <= segment.max_index())
+        .ok_or_else(|| TranslateError::IndexOutOfRange {
+            segment,
+            index,
+            span: span.clone(),
+        })
+}
+
+// the number of locals of a function or arguments of a call as written
+pub(crate) fn check_count(command: &str, count: i32, span: &Span) -> Result<u16, TranslateError> {
+    u16::try_from(count)
+        .ok()
+        .filter(|&count| i32::from(count) <= MAX_COUNT)
+        .ok_or_else(|| TranslateError::CountOutOfRange {
+            command: command.to_string(),
+            count,
+            span: span.clone(),
+        })
+}
+
+// the parser already checks every command it reads, this checks commands put together
+// some other way before they get translated or interpreted
+pub fn validate_vm_files(vm_files: &[VmFile]) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::new();
+    for parsed_command in vm_files.iter().flat_map(|vm_file| &vm_file.commands) {
+        let span = &parsed_command.span;
+        let result = match &parsed_command.command {
+            VmCommand::Push { segment, index } => {
+                check_index("push", *segment, i32::from(*index), span).map(|_| ())
+            }
+            VmCommand::Pop { segment, index } => {
+                check_index("pop", *segment, i32::from(*index), span).map(|_| ())
+            }
+            VmCommand::Function { nlocals: count, .. } => {
+                check_count("function", i32::from(*count), span).map(|_| ())
+            }
+            VmCommand::Call { nargs: count, .. } => {
+                check_count("call", i32::from(*count), span).map(|_| ())
+            }
+            _ => Ok(()),
+        };
+        if let Err(error) = result {
+            diagnostics.push(error);
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_command_symbol_table, ParsedCommand, VmCodeParser};
+
+    #[test]
+    fn validate_parsed_ranges() {
+        let command_symbol_table = get_command_symbol_table();
+        let test_parser = VmCodeParser::new();
+        let input = "push constant -5\npush constant 40000\npop temp 9\npush pointer 2\npop constant 3\npush local -1\nfunction Foo.bar -1\ncall Foo.bar 40000\npush constant 32767\npop temp 7\npush pointer 1\ncall Foo.bar 0";
+        let diagnostics = test_parser
+            .parse(input, "Foo.vm", &command_symbol_table)
+            .expect_err("Should be invalid");
+
+        let messages: Vec<String> = diagnostics
+            .iter()
+            .map(|error| error.to_string().lines().next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "error: index -5 is out of range for segment `constant`, expected 0 to 32767",
+                "error: index 40000 is out of range for segment `constant`, expected 0 to 32767",
+                "error: index 9 is out of range for segment `temp`, expected 0 to 7",
+                "error: index 2 is out of range for segment `pointer`, expected 0 to 1",
+                "error: invalid segment `constant` for `pop`",
+                "error: index -1 is out of range for segment `local`, expected 0 to 32767",
+                "error: `function` takes 0 to 32767 locals, not -1",
+                "error: `call` takes 0 to 32767 arguments, not 40000",
+            ],
+            messages
+        );
+        let lines: Vec<usize> = diagnostics
+            .iter()
+            .filter_map(|error| error.span())
+            .map(|span| span.line)
+            .collect();
+        assert_eq!((1..=8).collect::<Vec<usize>>(), lines);
+    }
+
+    #[test]
+    fn validate_constructed_commands() {
+        let commands = [
+            VmCommand::Push {
+                segment: Segment::Temp,
+                index: 8,
+            },
+            VmCommand::Pop {
+                segment: Segment::Constant,
+                index: 0,
+            },
+            VmCommand::Call {
+                name: String::from("Foo.bar"),
+                nargs: 40000,
+            },
+            VmCommand::Push {
+                segment: Segment::Pointer,
+                index: 1,
+            },
+        ]
+        .map(|command| ParsedCommand::new(command, Span::default()));
+        let diagnostics = validate_vm_files(&[VmFile::new("Foo", commands.to_vec())])
+            .expect_err("Should be invalid");
+
+        assert_eq!(3, diagnostics.len());
+        assert!(validate_vm_files(&[VmFile::new("Foo", commands[3..].to_vec())]).is_ok());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::call_graph::file_node_name;
+use crate::{CallGraph, ParsedCommand, Segment, VmCommand, VmFile};
+use std::collections::HashSet;
+
+// largest value push constant accepts
+const MAX_CONSTANT: i16 = 32767;
+
+// rewrites the parsed commands of a whole program before code generation:
+// arithmetic on constants is folded into a single push constant and
+// a value pushed only to be popped into a temp register nothing reads is dropped,
+// so after -O2 temp can hold an older value than the unoptimized program leaves there
+pub fn optimize_vm_files(vm_files: Vec<VmFile>) -> Vec<VmFile> {
+    // temp is global, so a write is only dead when no file ever pushes that register
+    let read_temps: HashSet<u16> = vm_files
+        .iter()
+        .flat_map(|vm_file| &vm_file.commands)
+        .filter_map(|parsed_command| match parsed_command.command {
+            VmCommand::Push {
+                segment: Segment::Temp,
+                index,
+            } => Some(index),
+            _ => None,
+        })
+        .collect();
+
+    vm_files
+        .into_iter()
+        .map(|vm_file| VmFile {
+            commands: optimize_commands(vm_file.commands, &read_temps),
+            ..vm_file
+        })
+        .collect()
+}
+
+// the optimized commands are built up one at a time so a folded result can be folded again
+fn optimize_commands(
+    vm_commands: Vec<ParsedCommand>,
+    read_temps: &HashSet<u16>,
+) -> Vec<ParsedCommand> {
+    let mut optimized_commands: Vec<ParsedCommand> = Vec::new();
+    for parsed_command in vm_commands {
+        optimized_commands.push(parsed_command);
+        while fold_constants(&mut optimized_commands) {}
+        drop_discarded_value(&mut optimized_commands, read_temps);
+    }
+
+    optimized_commands
+}
+
+fn fold_constants(vm_commands: &mut Vec<ParsedCommand>) -> bool {
+    let commands: Vec<&VmCommand> = vm_commands
+        .iter()
+        .rev()
+        .take(3)
+        .map(|parsed_command| &parsed_command.command)
+        .collect();
+    let (folded_value, folded_count) = match commands[..] {
+        [VmCommand::Arithmetic(op), VmCommand::Push {
+            segment: Segment::Constant,
+            index: y,
+        }, VmCommand::Push {
+            segment: Segment::Constant,
+            index: x,
+        }] if !op.is_unary() => (op.apply(*x as i16, *y as i16), 3),
+        [VmCommand::Arithmetic(op), VmCommand::Push {
+            segment: Segment::Constant,
+            index: y,
+        }, ..]
+            if op.is_unary() =>
+        {
+            (op.apply(0, *y as i16), 2)
+        }
+        _ => return false,
+    };
+    if !(0..=MAX_CONSTANT).contains(&folded_value) {
+        return false;
+    }
+
+    let first_command = vm_commands.len() - folded_count;
+    let span = vm_commands[first_command].span.clone();
+    vm_commands.truncate(first_command);
+    vm_commands.push(ParsedCommand::new(
+        VmCommand::Push {
+            segment: Segment::Constant,
+            index: folded_value as u16,
+        },
+        span,
+    ));
+    true
+}
+
+fn drop_discarded_value(vm_commands: &mut Vec<ParsedCommand>, read_temps: &HashSet<u16>) {
+    let commands: Vec<&VmCommand> = vm_commands
+        .iter()
+        .rev()
+        .take(2)
+        .map(|parsed_command| &parsed_command.command)
+        .collect();
+    if let [VmCommand::Pop {
+        segment: Segment::Temp,
+        index,
+    }, VmCommand::Push { .. }] = commands[..]
+    {
+        if !read_temps.contains(index) {
+            vm_commands.truncate(vm_commands.len() - 2);
+        }
+    }
+}
+
+// drops the body of every function the roots can never call, directly or through other
+// functions, and gives back the names of the dropped functions in order of declaration
+// code outside of any function always runs, so whatever it calls is kept too
+pub fn remove_unreachable_functions(
+    vm_files: Vec<VmFile>,
+    roots: &[String],
+) -> (Vec<VmFile>, Vec<String>) {
+    let call_graph = CallGraph::new(&vm_files);
+    let file_nodes: Vec<String> = vm_files
+        .iter()
+        .map(|vm_file| file_node_name(&vm_file.name))
+        .collect();
+    let reachable_functions =
+        call_graph.reachable_from(roots.iter().chain(&file_nodes).map(String::as_str));
+
+    let mut removed_functions = Vec::new();
+    let vm_files = vm_files
+        .into_iter()
+        .map(|vm_file| {
+            // a function body lasts until the next function declaration
+            let mut is_reachable = true;
+            let commands = vm_file
+                .commands
+                .into_iter()
+                .filter(|parsed_command| {
+                    if let VmCommand::Function { name, .. } = &parsed_command.command {
+                        is_reachable = reachable_functions.contains(name);
+                        if !is_reachable {
+                            removed_functions.push(name.clone());
+                        }
+                    }
+                    is_reachable
+                })
+                .collect();
+            VmFile {
+                commands,
+                ..vm_file
+            }
+        })
+        .collect();
+
+    (vm_files, removed_functions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_vm_fixture;
+
+    fn optimize_fixture(vm_files: &[(&str, &str)]) -> Vec<String> {
+        optimize_vm_files(parse_vm_fixture(vm_files))
+            .iter()
+            .flat_map(|vm_file| &vm_file.commands)
+            .map(|parsed_command| parsed_command.command.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn fold_constant_arithmetic() {
+        let vm_code = "push constant 7\npush constant 8\nadd\npush constant 5\nsub\nneg\npush constant 3\npush constant 4\nlt\npush constant 12\npush constant 10\nand\nnot";
+
+        assert_eq!(
+            vec![
+                "push constant 10",
+                "neg",
+                "push constant 3",
+                "push constant 4",
+                "lt",
+                "push constant 8",
+                "not"
+            ],
+            optimize_fixture(&[("Main", vm_code)])
+        );
+    }
+
+    #[test]
+    fn fold_keeps_labels_and_other_segments() {
+        let vm_code =
+            "push constant 1\nlabel LOOP\npush constant 2\nadd\npush local 0\npush constant 2\nadd";
+
+        assert_eq!(
+            vec![
+                "push constant 1",
+                "label LOOP",
+                "push constant 2",
+                "add",
+                "push local 0",
+                "push constant 2",
+                "add"
+            ],
+            optimize_fixture(&[("Main", vm_code)])
+        );
+    }
+
+    #[test]
+    fn drop_values_popped_into_unread_temp() {
+        let main_code =
+            "push local 0\npop temp 0\ncall Main.run 0\npop temp 0\npush constant 1\npop temp 1";
+        let other_code = "push temp 1\npop local 0";
+
+        assert_eq!(
+            vec![
+                "call Main.run 0",
+                "pop temp 0",
+                "push constant 1",
+                "pop temp 1",
+                "push temp 1",
+                "pop local 0"
+            ],
+            optimize_fixture(&[("Main", main_code), ("Other", other_code)])
+        );
+    }
+
+    #[test]
+    fn remove_functions_unreachable_from_roots() {
+        let sys_code = "function Sys.init 0\ncall Main.used 0\nlabel END\ngoto END";
+        let main_code = "push constant 1\ncall Main.top 1\nfunction Main.used 0\ncall Main.used 0\nreturn\nfunction Main.unused 0\ncall Main.other 0\nreturn\nfunction Main.top 1\nreturn\nfunction Main.other 0\nreturn\nfunction Main 0\nreturn";
+        let vm_files = parse_vm_fixture(&[("Sys", sys_code), ("Main", main_code)]);
+
+        let (kept_files, removed_functions) =
+            remove_unreachable_functions(vm_files.clone(), &[String::from("Sys.init")]);
+        // a function named like the file is not the code outside of functions
+        assert_eq!(
+            vec![
+                String::from("Main.unused"),
+                String::from("Main.other"),
+                String::from("Main")
+            ],
+            removed_functions
+        );
+        let kept_commands: Vec<String> = kept_files[1]
+            .commands
+            .iter()
+            .map(|parsed_command| parsed_command.command.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "push constant 1",
+                "call Main.top 1",
+                "function Main.used 0",
+                "call Main.used 0",
+                "return",
+                "function Main.top 1",
+                "return"
+            ],
+            kept_commands
+        );
+
+        let (_, removed_functions) =
+            remove_unreachable_functions(vm_files, &[String::from("Main.unused")]);
+        assert_eq!(
+            vec![
+                String::from("Sys.init"),
+                String::from("Main.used"),
+                String::from("Main")
+            ],
+            removed_functions
+        );
+    }
+}